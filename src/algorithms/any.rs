@@ -0,0 +1,122 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::eddsa::*;
+use super::es256::*;
+use super::es256k::*;
+use super::hmac::*;
+use super::rsa::*;
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::token::*;
+
+macro_rules! verification_keys {
+    ($($variant:ident($key_type:ty),)*) => {
+        /// A borrowed key of any type that can verify tokens
+        ///
+        /// This is useful to accept tokens signed using different algorithms, for example while
+        /// migrating from one algorithm to another, without requiring key identifiers.
+        #[derive(Clone, Copy)]
+        pub enum VerificationKey<'a> {
+            $(
+                #[doc = concat!("`", stringify!($variant), "` key")]
+                $variant(&'a $key_type),
+            )*
+        }
+
+        impl<'a> VerificationKey<'a> {
+            /// The JWT algorithm name
+            pub fn algorithm(&self) -> &'static str {
+                match self {
+                    $(VerificationKey::$variant(_) => stringify!($variant),)*
+                }
+            }
+
+            /// The key identifier, if one has been attached
+            pub fn key_id(&self) -> &'a Option<String> {
+                match self {
+                    $(VerificationKey::$variant(key) => key.key_id(),)*
+                }
+            }
+
+            /// Verify a token using options that can be shared across multiple verifications
+            pub fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+                &self,
+                token: &str,
+                options: &VerificationOptions,
+            ) -> Result<JWTClaims<CustomClaims>, Error> {
+                match self {
+                    $(VerificationKey::$variant(key) => key.verify_token_with_options(token, options),)*
+                }
+            }
+        }
+
+        $(
+            impl<'a> From<&'a $key_type> for VerificationKey<'a> {
+                fn from(key: &'a $key_type) -> Self {
+                    VerificationKey::$variant(key)
+                }
+            }
+        )*
+    };
+}
+
+verification_keys! {
+    HS256(HS256Key),
+    HS384(HS384Key),
+    HS512(HS512Key),
+    RS256(RS256PublicKey),
+    RS384(RS384PublicKey),
+    RS512(RS512PublicKey),
+    PS256(PS256PublicKey),
+    PS384(PS384PublicKey),
+    PS512(PS512PublicKey),
+    ES256(ES256PublicKey),
+    ES256K(ES256kPublicKey),
+    EdDSA(Ed25519PublicKey),
+}
+
+impl<'a> VerificationKey<'a> {
+    /// Verify a token
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+
+    /// Verify a token using the keys matching its algorithm, out of a set of keys of any type
+    ///
+    /// Keys whose algorithm differs from the one in the token header are not tried. If no key
+    /// uses that algorithm, `JWTError::NoMatchingKey` is returned. Otherwise, the matching keys
+    /// are tried in order, until one of them verifies the signature or the authentication tag.
+    /// The result for that key is returned, even if the claims are then rejected, so that the
+    /// actual reason isn't replaced by the signature failures of the remaining keys.
+    pub fn verify_token_with_any_key<CustomClaims: Serialize + DeserializeOwned>(
+        keys: &[VerificationKey<'a>],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        let metadata = Token::decode_metadata(token)?;
+        let mut res = Err(JWTError::NoMatchingKey.into());
+        for key in keys
+            .iter()
+            .filter(|key| key.algorithm() == metadata.algorithm())
+        {
+            res = key.verify_token_with_options(token, &options);
+            let signature_failed = matches!(
+                res.as_ref()
+                    .err()
+                    .and_then(|e| e.downcast_ref::<JWTError>()),
+                Some(JWTError::InvalidSignature) | Some(JWTError::InvalidAuthenticationTag)
+            );
+            if !signature_failed {
+                break;
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,281 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::str::FromStr;
+
+use super::es256::*;
+use super::es256k::*;
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+
+/// An elliptic curve supported for ECDSA signatures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ECCurve {
+    /// NIST P-256, for the `ES256` algorithm
+    P256,
+    /// secp256k1, for the `ES256K` algorithm
+    Secp256k1,
+}
+
+impl ECCurve {
+    /// The curve name, as used in JWKs
+    pub fn name(&self) -> &'static str {
+        match self {
+            ECCurve::P256 => "P-256",
+            ECCurve::Secp256k1 => "secp256k1",
+        }
+    }
+
+    /// The JWT algorithm name for signatures using this curve
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            ECCurve::P256 => "ES256",
+            ECCurve::Secp256k1 => "ES256K",
+        }
+    }
+}
+
+/// Curves can be parsed from their name (`P-256`, `secp256k1`) or their JWT algorithm name
+impl FromStr for ECCurve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "P-256" | "ES256" => Ok(ECCurve::P256),
+            "secp256k1" | "ES256K" => Ok(ECCurve::Secp256k1),
+            _ => bail!(JWTError::UnsupportedCurve),
+        }
+    }
+}
+
+fn is_unsupported_curve(e: &Error) -> bool {
+    e.downcast_ref::<KeyImportErrorReason>() == Some(&KeyImportErrorReason::UnsupportedCurve)
+}
+
+/// A key pair for any of the supported ECDSA curves
+///
+/// This is useful when the curve is only known at runtime. The per-curve types can be used
+/// when the curve is known in advance.
+#[derive(Debug, Clone)]
+pub enum ECDSAKeyPair {
+    /// P-256 key pair, for the `ES256` algorithm
+    ES256(ES256KeyPair),
+    /// secp256k1 key pair, for the `ES256K` algorithm
+    ES256K(ES256kKeyPair),
+}
+
+impl ECDSAKeyPair {
+    /// Generate a new key pair on the given curve
+    pub fn generate(curve: ECCurve) -> Self {
+        match curve {
+            ECCurve::P256 => ECDSAKeyPair::ES256(ES256KeyPair::generate()),
+            ECCurve::Secp256k1 => ECDSAKeyPair::ES256K(ES256kKeyPair::generate()),
+        }
+    }
+
+    /// Import a key pair on the given curve from its raw representation
+    pub fn from_bytes(curve: ECCurve, raw: &[u8]) -> Result<Self, Error> {
+        match curve {
+            ECCurve::P256 => Ok(ES256KeyPair::from_bytes(raw)?.into()),
+            ECCurve::Secp256k1 => Ok(ES256kKeyPair::from_bytes(raw)?.into()),
+        }
+    }
+
+    /// Export the key pair using its raw representation, that doesn't include the curve
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ECDSAKeyPair::ES256(key_pair) => key_pair.to_bytes(),
+            ECDSAKeyPair::ES256K(key_pair) => key_pair.to_bytes(),
+        }
+    }
+
+    /// Import a key pair from an unencrypted PKCS#8 PEM document, on any supported curve
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        match ES256KeyPair::from_pem(pem) {
+            Err(e) if is_unsupported_curve(&e) => Ok(ES256kKeyPair::from_pem(pem)?.into()),
+            key_pair => Ok(key_pair?.into()),
+        }
+    }
+
+    /// Export the key pair as a PKCS#8 PEM document
+    pub fn to_pem(&self) -> String {
+        match self {
+            ECDSAKeyPair::ES256(key_pair) => key_pair.to_pem(),
+            ECDSAKeyPair::ES256K(key_pair) => key_pair.to_pem(),
+        }
+    }
+
+    /// The curve of the key pair
+    pub fn curve(&self) -> ECCurve {
+        match self {
+            ECDSAKeyPair::ES256(_) => ECCurve::P256,
+            ECDSAKeyPair::ES256K(_) => ECCurve::Secp256k1,
+        }
+    }
+
+    /// The JWT algorithm name
+    pub fn algorithm(&self) -> &'static str {
+        self.curve().algorithm()
+    }
+
+    /// The key identifier, if one has been attached
+    pub fn key_id(&self) -> &Option<String> {
+        match self {
+            ECDSAKeyPair::ES256(key_pair) => key_pair.key_id(),
+            ECDSAKeyPair::ES256K(key_pair) => key_pair.key_id(),
+        }
+    }
+
+    /// Attach a key identifier
+    pub fn with_key_id(self, key_id: &str) -> Self {
+        match self {
+            ECDSAKeyPair::ES256(key_pair) => ECDSAKeyPair::ES256(key_pair.with_key_id(key_id)),
+            ECDSAKeyPair::ES256K(key_pair) => ECDSAKeyPair::ES256K(key_pair.with_key_id(key_id)),
+        }
+    }
+
+    /// Return the public key
+    pub fn public_key(&self) -> ECDSAPublicKey {
+        match self {
+            ECDSAKeyPair::ES256(key_pair) => ECDSAPublicKey::ES256(key_pair.public_key()),
+            ECDSAKeyPair::ES256K(key_pair) => ECDSAPublicKey::ES256K(key_pair.public_key()),
+        }
+    }
+
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
+    /// Sign claims
+    pub fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        match self {
+            ECDSAKeyPair::ES256(key_pair) => key_pair.sign(claims),
+            ECDSAKeyPair::ES256K(key_pair) => key_pair.sign(claims),
+        }
+    }
+}
+
+impl From<ES256KeyPair> for ECDSAKeyPair {
+    fn from(key_pair: ES256KeyPair) -> Self {
+        ECDSAKeyPair::ES256(key_pair)
+    }
+}
+
+impl From<ES256kKeyPair> for ECDSAKeyPair {
+    fn from(key_pair: ES256kKeyPair) -> Self {
+        ECDSAKeyPair::ES256K(key_pair)
+    }
+}
+
+/// A public key for any of the supported ECDSA curves
+///
+/// This verifies tokens from issuers that sign with different curves, without having to branch
+/// on the algorithm of the token. The algorithm of a token must still match the curve of the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ECDSAPublicKey {
+    /// P-256 public key, for the `ES256` algorithm
+    ES256(ES256PublicKey),
+    /// secp256k1 public key, for the `ES256K` algorithm
+    ES256K(ES256kPublicKey),
+}
+
+impl ECDSAPublicKey {
+    /// Import a public key on the given curve from its raw representation
+    pub fn from_bytes(curve: ECCurve, raw: &[u8]) -> Result<Self, Error> {
+        match curve {
+            ECCurve::P256 => Ok(ES256PublicKey::from_bytes(raw)?.into()),
+            ECCurve::Secp256k1 => Ok(ES256kPublicKey::from_bytes(raw)?.into()),
+        }
+    }
+
+    /// Export the public key using its raw representation, that doesn't include the curve
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ECDSAPublicKey::ES256(pk) => pk.to_bytes(),
+            ECDSAPublicKey::ES256K(pk) => pk.to_bytes(),
+        }
+    }
+
+    /// Import a public key from a PEM document, on any supported curve
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        match ES256PublicKey::from_pem(pem) {
+            Err(e) if is_unsupported_curve(&e) => Ok(ES256kPublicKey::from_pem(pem)?.into()),
+            pk => Ok(pk?.into()),
+        }
+    }
+
+    /// Export the public key as a PEM document
+    pub fn to_pem(&self) -> String {
+        match self {
+            ECDSAPublicKey::ES256(pk) => pk.to_pem(),
+            ECDSAPublicKey::ES256K(pk) => pk.to_pem(),
+        }
+    }
+
+    /// The curve of the public key
+    pub fn curve(&self) -> ECCurve {
+        match self {
+            ECDSAPublicKey::ES256(_) => ECCurve::P256,
+            ECDSAPublicKey::ES256K(_) => ECCurve::Secp256k1,
+        }
+    }
+
+    /// The JWT algorithm name
+    pub fn algorithm(&self) -> &'static str {
+        self.curve().algorithm()
+    }
+
+    /// The key identifier, if one has been attached
+    pub fn key_id(&self) -> &Option<String> {
+        match self {
+            ECDSAPublicKey::ES256(pk) => pk.key_id(),
+            ECDSAPublicKey::ES256K(pk) => pk.key_id(),
+        }
+    }
+
+    /// Verify a token
+    ///
+    /// Tokens signed using an algorithm that doesn't match the curve of the key are rejected
+    /// with `JWTError::AlgorithmMismatch`.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications
+    pub fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        match self {
+            ECDSAPublicKey::ES256(pk) => pk.verify_token_with_options(token, options),
+            ECDSAPublicKey::ES256K(pk) => pk.verify_token_with_options(token, options),
+        }
+    }
+}
+
+impl From<ES256PublicKey> for ECDSAPublicKey {
+    fn from(pk: ES256PublicKey) -> Self {
+        ECDSAPublicKey::ES256(pk)
+    }
+}
+
+impl From<ES256kPublicKey> for ECDSAPublicKey {
+    fn from(pk: ES256kPublicKey) -> Self {
+        ECDSAPublicKey::ES256K(pk)
+    }
+}
@@ -1,14 +1,25 @@
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
 use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+use zeroize::Zeroize;
 
 use crate::claims::*;
 use crate::common::*;
 use crate::error::*;
 use crate::jwt_header::*;
+use crate::pkcs8;
 use crate::token::*;
 
+fn ed25519_import_error_reason(raw: &[u8], expected_len: usize) -> KeyImportErrorReason {
+    if raw.len() < expected_len {
+        KeyImportErrorReason::Truncated
+    } else {
+        KeyImportErrorReason::Invalid
+    }
+}
+
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Edwards25519PublicKey(ed25519_compact::PublicKey);
 
 impl AsRef<ed25519_compact::PublicKey> for Edwards25519PublicKey {
@@ -19,20 +30,60 @@ impl AsRef<ed25519_compact::PublicKey> for Edwards25519PublicKey {
 
 impl Edwards25519PublicKey {
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
-        let ed25519_pk = ed25519_compact::PublicKey::from_slice(raw);
-        Ok(Edwards25519PublicKey(
-            ed25519_pk.map_err(|_| JWTError::InvalidPublicKey)?,
-        ))
+        let ed25519_pk = ed25519_compact::PublicKey::from_slice(raw)
+            .map_err(|_| ed25519_import_error_reason(raw, 32).public_key_error())?;
+        Ok(Edwards25519PublicKey(ed25519_pk))
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         self.0.as_ref().to_vec()
     }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let raw =
+            pkcs8::spki_public_key(der, None).map_err(KeyImportErrorReason::public_key_error)?;
+        Self::from_bytes(&raw)
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        pkcs8::ed25519_public_key_der(self.0.as_ref())
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let der = pkcs8::pem_contents(pem, "PUBLIC KEY")
+            .map_err(KeyImportErrorReason::public_key_error)?;
+        Self::from_der(&der)
+    }
+
+    pub fn to_pem(&self) -> String {
+        pkcs8::to_pem("PUBLIC KEY", self.to_der())
+    }
+
+    /// RFC 7638 thumbprint of the public key
+    pub(crate) fn thumbprint(&self) -> String {
+        jwk_thumbprint(&[
+            ("crv", "Ed25519"),
+            ("kty", "OKP"),
+            (
+                "x",
+                &Base64UrlSafeNoPadding::encode_to_string(self.0.as_ref()).unwrap(),
+            ),
+        ])
+    }
 }
 
 #[doc(hidden)]
+#[derive(Clone)]
 pub struct Edwards25519KeyPair(ed25519_compact::KeyPair);
 
+impl fmt::Debug for Edwards25519KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Edwards25519KeyPair")
+            .field(&format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl AsRef<ed25519_compact::KeyPair> for Edwards25519KeyPair {
     fn as_ref(&self) -> &ed25519_compact::KeyPair {
         &self.0
@@ -41,7 +92,8 @@ impl AsRef<ed25519_compact::KeyPair> for Edwards25519KeyPair {
 
 impl Edwards25519KeyPair {
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
-        let ed25519_key_pair = ed25519_compact::KeyPair::from_slice(raw)?;
+        let ed25519_key_pair = ed25519_compact::KeyPair::from_slice(raw)
+            .map_err(|_| ed25519_import_error_reason(raw, 64).key_pair_error())?;
         Ok(Edwards25519KeyPair(ed25519_key_pair))
     }
 
@@ -49,6 +101,37 @@ impl Edwards25519KeyPair {
         self.0.to_vec()
     }
 
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let mut seed = pkcs8::ed25519_seed(der).map_err(KeyImportErrorReason::key_pair_error)?;
+        let key_pair = ed25519_compact::Seed::from_slice(&seed)
+            .map(|seed| Edwards25519KeyPair(ed25519_compact::KeyPair::from_seed(seed)))
+            .map_err(|_| KeyImportErrorReason::Invalid.key_pair_error());
+        seed.zeroize();
+        key_pair
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        pkcs8::ed25519_private_key_der(self.0.sk.seed().as_ref())
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let mut der = pkcs8::pem_contents(pem, "PRIVATE KEY")
+            .map_err(KeyImportErrorReason::key_pair_error)?;
+        let key_pair = Self::from_der(&der);
+        der.zeroize();
+        key_pair
+    }
+
+    pub fn to_pem(&self) -> String {
+        pkcs8::to_pem("PRIVATE KEY", self.to_der())
+    }
+
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let ed25519_key_pair =
+            ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(*seed));
+        Edwards25519KeyPair(ed25519_key_pair)
+    }
+
     pub fn public_key(&self) -> Edwards25519PublicKey {
         let ed25519_pk = self.0.pk;
         Edwards25519PublicKey(ed25519_pk)
@@ -60,9 +143,31 @@ impl Edwards25519KeyPair {
     }
 }
 
+/// Properties shared by Ed25519 key pairs and public keys
 #[doc(hidden)]
-pub trait EdDSAKeyPairLike {
+pub trait EdDSAKeyLike {
     fn jwt_alg_name() -> &'static str;
+
+    /// The JWT algorithm name
+    fn algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    /// Size of the hash function output, in bits
+    fn hash_bits(&self) -> usize {
+        512
+    }
+
+    /// Size of the key, in bits
+    ///
+    /// Ed25519 keys are 256 bit long.
+    fn key_strength_bits(&self) -> usize {
+        256
+    }
+}
+
+#[doc(hidden)]
+pub trait EdDSAKeyPairLike: EdDSAKeyLike {
     fn key_pair(&self) -> &Edwards25519KeyPair;
     fn key_id(&self) -> &Option<String>;
 
@@ -70,20 +175,95 @@ pub trait EdDSAKeyPairLike {
         &self,
         claims: JWTClaims<CustomClaims>,
     ) -> Result<String, Error> {
-        let mut jwt_header = JWTHeader::default();
+        self.sign_returning_claims(claims)
+            .map(|(token, _claims)| token)
+    }
+
+    /// Sign claims, and return the token along with the claims exactly as they were serialized
+    fn sign_returning_claims<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<(String, JWTClaims<CustomClaims>), Error> {
+        let claims = claims.truncate_timestamps();
+        let token = self.sign_with_header(&claims, JWTHeader::default())?;
+        Ok((token, claims))
+    }
+
+    /// Sign claims, using a custom header
+    ///
+    /// The algorithm is always set to the one of the key. So is the key identifier, unless the
+    /// header already includes one.
+    fn sign_with_header<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: &JWTClaims<CustomClaims>,
+        mut jwt_header: JWTHeader,
+    ) -> Result<String, Error> {
         jwt_header.algorithm = Self::jwt_alg_name().to_string();
-        jwt_header.key_id = self.key_id().clone();
+        if jwt_header.key_id.is_none() {
+            jwt_header.key_id = self.key_id().clone();
+        }
         Token::build(&jwt_header, claims, |authenticated| {
-            let noise = ed25519_compact::Noise::generate();
-            let signature = self.key_pair().as_ref().sk.sign(authenticated, Some(noise));
-            Ok(signature.to_vec())
+            self.signature(authenticated.as_bytes())
+        })
+    }
+
+    /// Sign claims, and attach an application-defined footer to the header
+    ///
+    /// The footer is not encrypted, but as part of the header, it is covered by the signature.
+    fn sign_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        footer: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            footer: Some(footer.to_string()),
+            ..Default::default()
+        };
+        self.sign_with_header(&claims, jwt_header)
+    }
+
+    /// Sign claims, and set the content type (`cty`) header member
+    ///
+    /// This is mainly useful for nested tokens, where `content_type` is set to `"JWT"`.
+    fn sign_with_content_type<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        content_type: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            content_type: Some(content_type.to_string()),
+            ..Default::default()
+        };
+        self.sign_with_header(&claims, jwt_header)
+    }
+
+    /// Sign an arbitrary payload, without encoding it, and detach it from the token (RFC 7797)
+    ///
+    /// The signature is computed over the raw payload, with the `b64` header member set to `false`.
+    /// The payload is not included in the token: it must be transmitted separately, and
+    /// provided again to `verify_unencoded_detached()`. Only detached payloads are supported, as
+    /// the compact serialization can't carry arbitrary bytes, such as a `.` in the payload.
+    fn sign_unencoded_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            algorithm: Self::jwt_alg_name().to_string(),
+            key_id: self.key_id().clone(),
+            ..Default::default()
+        };
+        Token::build_unencoded_detached(jwt_header, payload, |signing_input| {
+            self.signature(signing_input)
         })
     }
+
+    /// Compute the signature of `signing_input`
+    fn signature(&self, signing_input: &[u8]) -> Result<Vec<u8>, Error> {
+        let noise = ed25519_compact::Noise::generate();
+        let signature = self.key_pair().as_ref().sk.sign(signing_input, Some(noise));
+        Ok(signature.to_vec())
+    }
 }
 
 #[doc(hidden)]
-pub trait EdDSAPublicKeyLike {
-    fn jwt_alg_name() -> &'static str;
+pub trait EdDSAPublicKeyLike: EdDSAKeyLike {
     fn public_key(&self) -> &Edwards25519PublicKey;
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
@@ -92,22 +272,127 @@ pub trait EdDSAPublicKeyLike {
         &self,
         token: &str,
         options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+
+    /// Verify a token, and return its claims along with the footer, if there is one
+    fn verify_token_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, Option<String>), Error> {
+        let (claims, metadata) = self.verify_token_with_metadata(token, options)?;
+        Ok((claims, metadata.footer().map(|x| x.to_string())))
+    }
+
+    /// Verify a token, and return its claims along with its metadata
+    ///
+    /// The metadata includes the header members, the length of the token, the number of claims
+    /// and the algorithm the token was verified with.
+    fn verify_token_with_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options_and_metadata(token, &options)
+    }
+
+    /// Verify a token, using a key chosen according to the token metadata
+    ///
+    /// `selector` is given the unverified metadata of the token, such as the key identifier
+    /// and the issuer, and returns the key to verify the token with, if there is one.
+    fn verify_token_with_key_selector<'a, CustomClaims: Serialize + DeserializeOwned>(
+        token: &str,
+        options: Option<VerificationOptions>,
+        selector: impl FnOnce(&TokenMetadata) -> Option<&'a Self>,
+    ) -> Result<JWTClaims<CustomClaims>, Error>
+    where
+        Self: Sized + 'a,
+    {
+        let metadata = Token::decode_metadata(token)?;
+        let key = selector(&metadata).ok_or(JWTError::NoMatchingKey)?;
+        key.verify_token(token, options)
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let ed25519_signature = ed25519_compact::Signature::from_slice(signature)?;
-                self.public_key()
-                    .as_ref()
-                    .verify(authenticated, &ed25519_signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications, and return
+    /// its claims along with its metadata
+    fn verify_token_with_options_and_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        Token::verify_with_metadata(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token, and return its standard claims, borrowed from `payload`
+    ///
+    /// The decoded payload is stored in `payload`, that can be reused across verifications to
+    /// avoid allocations. Custom claims are ignored, as well as the `lenient_audiences` and
+    /// `rfc3339_timestamps` verification options.
+    fn verify_token_borrowed<'b>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+        payload: &'b mut Vec<u8>,
+    ) -> Result<BorrowedClaims<'b>, Error> {
+        Token::verify_borrowed(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            payload,
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token created with `sign_unencoded_detached()`, along with its payload
+    fn verify_unencoded_detached(
+        &self,
+        token: &str,
+        payload: &[u8],
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_unencoded_detached(
+            Self::jwt_alg_name(),
+            token,
+            payload,
+            &options,
+            |signing_input, signature| self.verify_signature(signing_input, signature),
         )
     }
 
+    /// Verify the signature of `signing_input`
+    fn verify_signature(&self, signing_input: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let ed25519_signature = ed25519_compact::Signature::from_slice(signature)?;
+        self.public_key()
+            .as_ref()
+            .verify(signing_input, &ed25519_signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
     fn create_key_id(&mut self) -> &str {
         self.set_key_id(
             Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
@@ -115,26 +400,48 @@ pub trait EdDSAPublicKeyLike {
             ))
             .unwrap(),
         );
-        &self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+        self.key_id().as_deref().unwrap()
     }
 }
 
+#[derive(Clone)]
 pub struct Ed25519KeyPair {
     key_pair: Edwards25519KeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for Ed25519KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Ed25519KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Ed25519PublicKey {
     pk: Edwards25519PublicKey,
     key_id: Option<String>,
 }
 
-impl EdDSAKeyPairLike for Ed25519KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for Ed25519PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for Ed25519PublicKey {}
+
+impl EdDSAKeyLike for Ed25519KeyPair {
     fn jwt_alg_name() -> &'static str {
         "EdDSA"
     }
+}
 
+impl EdDSAKeyPairLike for Ed25519KeyPair {
     fn key_pair(&self) -> &Edwards25519KeyPair {
         &self.key_pair
     }
@@ -145,6 +452,9 @@ impl EdDSAKeyPairLike for Ed25519KeyPair {
 }
 
 impl Ed25519KeyPair {
+    /// Import a key pair from its 64 bytes representation
+    ///
+    /// This is the libsodium format: the 32 bytes seed, followed by the 32 bytes public key.
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
         Ok(Ed25519KeyPair {
             key_pair: Edwards25519KeyPair::from_bytes(raw)?,
@@ -152,10 +462,45 @@ impl Ed25519KeyPair {
         })
     }
 
+    /// Create a key pair from a 32 bytes seed
+    ///
+    /// The seed is the secret from which the actual signing key and the public key are derived.
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::from_seed(seed),
+            key_id: None,
+        }
+    }
+
+    /// Export the key pair as 64 bytes: the seed, followed by the public key
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key_pair.to_bytes()
     }
 
+    /// Import a key pair from a DER-encoded PKCS#8 document
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    /// Import a key pair from an unencrypted PKCS#8 PEM document
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
     pub fn public_key(&self) -> Ed25519PublicKey {
         Ed25519PublicKey {
             pk: self.key_pair.public_key(),
@@ -163,6 +508,17 @@ impl Ed25519KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate() -> Self {
         Ed25519KeyPair {
             key_pair: Edwards25519KeyPair::generate(),
@@ -174,13 +530,21 @@ impl Ed25519KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl EdDSAPublicKeyLike for Ed25519PublicKey {
+impl EdDSAKeyLike for Ed25519PublicKey {
     fn jwt_alg_name() -> &'static str {
         "EdDSA"
     }
+}
 
+impl EdDSAPublicKeyLike for Ed25519PublicKey {
     fn public_key(&self) -> &Edwards25519PublicKey {
         &self.pk
     }
@@ -195,6 +559,7 @@ impl EdDSAPublicKeyLike for Ed25519PublicKey {
 }
 
 impl Ed25519PublicKey {
+    /// Import a public key from its 32 bytes representation
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
         Ok(Ed25519PublicKey {
             pk: Edwards25519PublicKey::from_bytes(raw)?,
@@ -206,6 +571,30 @@ impl Ed25519PublicKey {
         self.pk.to_bytes()
     }
 
+    /// Import a public key from a DER-encoded `SubjectPublicKeyInfo` structure
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(Ed25519PublicKey {
+            pk: Edwards25519PublicKey::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    /// Import a public key from a PEM document
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(Ed25519PublicKey {
+            pk: Edwards25519PublicKey::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -1,16 +1,20 @@
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
 use p256::ecdsa::{self, signature::DigestVerifier as _, signature::RandomizedDigestSigner as _};
+use rand::{CryptoRng, RngCore};
 use serde::{de::DeserializeOwned, Serialize};
 use std::convert::TryFrom;
+use std::fmt;
+use zeroize::Zeroize;
 
 use crate::claims::*;
 use crate::common::*;
 use crate::error::*;
 use crate::jwt_header::*;
+use crate::pkcs8;
 use crate::token::*;
 
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct P256PublicKey(ecdsa::VerifyKey);
 
 impl AsRef<ecdsa::VerifyKey> for P256PublicKey {
@@ -21,27 +25,85 @@ impl AsRef<ecdsa::VerifyKey> for P256PublicKey {
 
 impl P256PublicKey {
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
-        let p256_pk = ecdsa::VerifyKey::new(raw).map_err(|_| JWTError::InvalidPublicKey)?;
+        let p256_pk = ecdsa::VerifyKey::new(raw)
+            .map_err(|_| ec_public_key_import_error_reason(raw).public_key_error())?;
         Ok(P256PublicKey(p256_pk))
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         self.0.to_encoded_point(true).as_bytes().to_vec()
     }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let raw = pkcs8::spki_public_key(der, Some(pkcs8::OID_PRIME256V1))
+            .map_err(KeyImportErrorReason::public_key_error)?;
+        Self::from_bytes(&raw)
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        let point = self.0.to_encoded_point(false);
+        pkcs8::ec_public_key_der(point.as_bytes(), pkcs8::OID_PRIME256V1)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let der = pkcs8::pem_contents(pem, "PUBLIC KEY")
+            .map_err(KeyImportErrorReason::public_key_error)?;
+        Self::from_der(&der)
+    }
+
+    pub fn to_pem(&self) -> String {
+        pkcs8::to_pem("PUBLIC KEY", self.to_der())
+    }
+
+    /// RFC 7638 thumbprint of the public key
+    pub(crate) fn thumbprint(&self) -> String {
+        let point = self.0.to_encoded_point(false);
+        let point = point.as_bytes();
+        jwk_thumbprint(&[
+            ("crv", "P-256"),
+            ("kty", "EC"),
+            (
+                "x",
+                &Base64UrlSafeNoPadding::encode_to_string(&point[1..33]).unwrap(),
+            ),
+            (
+                "y",
+                &Base64UrlSafeNoPadding::encode_to_string(&point[33..]).unwrap(),
+            ),
+        ])
+    }
 }
 
 #[doc(hidden)]
 pub struct P256KeyPair(ecdsa::SigningKey);
 
+impl fmt::Debug for P256KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("P256KeyPair")
+            .field(&format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl AsRef<ecdsa::SigningKey> for P256KeyPair {
     fn as_ref(&self) -> &ecdsa::SigningKey {
         &self.0
     }
 }
 
+impl Clone for P256KeyPair {
+    fn clone(&self) -> Self {
+        let mut raw = self.0.to_bytes();
+        let sk = ecdsa::SigningKey::new(&raw).expect("Valid key");
+        raw.iter_mut().for_each(|x| x.zeroize());
+        P256KeyPair(sk)
+    }
+}
+
 impl P256KeyPair {
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
-        let p256_key_pair = ecdsa::SigningKey::new(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        let p256_key_pair = ecdsa::SigningKey::new(raw)
+            .map_err(|_| ec_key_pair_import_error_reason(raw).key_pair_error())?;
         Ok(P256KeyPair(p256_key_pair))
     }
 
@@ -49,6 +111,44 @@ impl P256KeyPair {
         self.0.to_bytes().to_vec()
     }
 
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let mut secret_key = pkcs8::ec_secret_key(der, pkcs8::OID_PRIME256V1)
+            .map_err(KeyImportErrorReason::key_pair_error)?;
+        let key_pair = Self::from_bytes(&secret_key);
+        secret_key.zeroize();
+        key_pair
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        let mut secret_key = self.to_bytes();
+        let der = pkcs8::ec_private_key_der(&secret_key, pkcs8::OID_PRIME256V1);
+        secret_key.zeroize();
+        der
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let mut der = pkcs8::pem_contents(pem, "PRIVATE KEY")
+            .map_err(KeyImportErrorReason::key_pair_error)?;
+        let key_pair = Self::from_der(&der);
+        der.zeroize();
+        key_pair
+    }
+
+    pub fn to_pem(&self) -> String {
+        pkcs8::to_pem("PRIVATE KEY", self.to_der())
+    }
+
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        let mut der =
+            pkcs8::decrypt_pem(pem, passphrase).map_err(KeyImportErrorReason::key_pair_error)?;
+        let secret_key = pkcs8::ec_secret_key(&der, pkcs8::OID_PRIME256V1);
+        der.zeroize();
+        let mut secret_key = secret_key.map_err(KeyImportErrorReason::key_pair_error)?;
+        let key_pair = Self::from_bytes(&secret_key);
+        secret_key.zeroize();
+        key_pair
+    }
+
     pub fn public_key(&self) -> P256PublicKey {
         let p256_pk = self.0.verify_key();
         P256PublicKey(p256_pk)
@@ -61,9 +161,31 @@ impl P256KeyPair {
     }
 }
 
+/// Properties shared by ECDSA P-256 key pairs and public keys
 #[doc(hidden)]
-pub trait ECDSAP256KeyPairLike {
+pub trait ECDSAP256KeyLike {
     fn jwt_alg_name() -> &'static str;
+
+    /// The JWT algorithm name
+    fn algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    /// Size of the hash function output, in bits
+    fn hash_bits(&self) -> usize {
+        256
+    }
+
+    /// Size of the key, in bits
+    ///
+    /// This is the size of the p256 curve.
+    fn key_strength_bits(&self) -> usize {
+        256
+    }
+}
+
+#[doc(hidden)]
+pub trait ECDSAP256KeyPairLike: ECDSAP256KeyLike {
     fn key_pair(&self) -> &P256KeyPair;
     fn key_id(&self) -> &Option<String>;
 
@@ -71,23 +193,117 @@ pub trait ECDSAP256KeyPairLike {
         &self,
         claims: JWTClaims<CustomClaims>,
     ) -> Result<String, Error> {
-        let mut jwt_header = JWTHeader::default();
+        self.sign_returning_claims(claims)
+            .map(|(token, _claims)| token)
+    }
+
+    /// Sign claims, and return the token along with the claims exactly as they were serialized
+    fn sign_returning_claims<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<(String, JWTClaims<CustomClaims>), Error> {
+        let claims = claims.truncate_timestamps();
+        let token = self.sign_with_header(&claims, JWTHeader::default())?;
+        Ok((token, claims))
+    }
+
+    /// Sign claims, using a custom random number generator for the ECDSA nonce
+    fn sign_with_rng<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<String, Error> {
+        let claims = claims.truncate_timestamps();
+        self.sign_with_header_and_rng(&claims, JWTHeader::default(), rng)
+    }
+
+    /// Sign claims, using a custom header
+    ///
+    /// The algorithm is always set to the one of the key. So is the key identifier, unless the
+    /// header already includes one.
+    fn sign_with_header<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: &JWTClaims<CustomClaims>,
+        jwt_header: JWTHeader,
+    ) -> Result<String, Error> {
+        self.sign_with_header_and_rng(claims, jwt_header, rand::thread_rng())
+    }
+
+    /// Sign claims, using a custom header and a custom random number generator for the ECDSA nonce
+    fn sign_with_header_and_rng<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: &JWTClaims<CustomClaims>,
+        mut jwt_header: JWTHeader,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<String, Error> {
         jwt_header.algorithm = Self::jwt_alg_name().to_string();
-        jwt_header.key_id = self.key_id().clone();
+        if jwt_header.key_id.is_none() {
+            jwt_header.key_id = self.key_id().clone();
+        }
         Token::build(&jwt_header, claims, |authenticated| {
-            let mut digest = hmac_sha256::Hash::new();
-            digest.update(authenticated.as_bytes());
-            let rng = rand::thread_rng();
-            let signature: ecdsa::Signature =
-                self.key_pair().as_ref().sign_digest_with_rng(rng, digest);
-            Ok(signature.as_ref().to_vec())
+            Ok(self.signature_with_rng(authenticated.as_bytes(), &mut rng))
+        })
+    }
+
+    /// Sign claims, and attach an application-defined footer to the header
+    ///
+    /// The footer is not encrypted, but as part of the header, it is covered by the signature.
+    fn sign_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        footer: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            footer: Some(footer.to_string()),
+            ..Default::default()
+        };
+        self.sign_with_header(&claims, jwt_header)
+    }
+
+    /// Sign claims, and set the content type (`cty`) header member
+    ///
+    /// This is mainly useful for nested tokens, where `content_type` is set to `"JWT"`.
+    fn sign_with_content_type<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        content_type: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            content_type: Some(content_type.to_string()),
+            ..Default::default()
+        };
+        self.sign_with_header(&claims, jwt_header)
+    }
+
+    /// Sign an arbitrary payload, without encoding it, and detach it from the token (RFC 7797)
+    ///
+    /// The signature is computed over the raw payload, with the `b64` header member set to `false`.
+    /// The payload is not included in the token: it must be transmitted separately, and
+    /// provided again to `verify_unencoded_detached()`. Only detached payloads are supported, as
+    /// the compact serialization can't carry arbitrary bytes, such as a `.` in the payload.
+    fn sign_unencoded_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            algorithm: Self::jwt_alg_name().to_string(),
+            key_id: self.key_id().clone(),
+            ..Default::default()
+        };
+        Token::build_unencoded_detached(jwt_header, payload, |signing_input| {
+            Ok(self.signature_with_rng(signing_input, rand::thread_rng()))
         })
     }
+
+    /// Compute the signature of `signing_input`, using `rng` for the ECDSA nonce
+    fn signature_with_rng(&self, signing_input: &[u8], rng: impl RngCore + CryptoRng) -> Vec<u8> {
+        let mut digest = hmac_sha256::Hash::new();
+        digest.update(signing_input);
+        let signature: ecdsa::Signature =
+            self.key_pair().as_ref().sign_digest_with_rng(rng, digest);
+        signature.as_ref().to_vec()
+    }
 }
 
 #[doc(hidden)]
-pub trait ECDSAP256PublicKeyLike {
-    fn jwt_alg_name() -> &'static str;
+pub trait ECDSAP256PublicKeyLike: ECDSAP256KeyLike {
     fn public_key(&self) -> &P256PublicKey;
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
@@ -96,25 +312,130 @@ pub trait ECDSAP256PublicKeyLike {
         &self,
         token: &str,
         options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+
+    /// Verify a token, and return its claims along with the footer, if there is one
+    fn verify_token_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, Option<String>), Error> {
+        let (claims, metadata) = self.verify_token_with_metadata(token, options)?;
+        Ok((claims, metadata.footer().map(|x| x.to_string())))
+    }
+
+    /// Verify a token, and return its claims along with its metadata
+    ///
+    /// The metadata includes the header members, the length of the token, the number of claims
+    /// and the algorithm the token was verified with.
+    fn verify_token_with_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options_and_metadata(token, &options)
+    }
+
+    /// Verify a token, using a key chosen according to the token metadata
+    ///
+    /// `selector` is given the unverified metadata of the token, such as the key identifier
+    /// and the issuer, and returns the key to verify the token with, if there is one.
+    fn verify_token_with_key_selector<'a, CustomClaims: Serialize + DeserializeOwned>(
+        token: &str,
+        options: Option<VerificationOptions>,
+        selector: impl FnOnce(&TokenMetadata) -> Option<&'a Self>,
+    ) -> Result<JWTClaims<CustomClaims>, Error>
+    where
+        Self: Sized + 'a,
+    {
+        let metadata = Token::decode_metadata(token)?;
+        let key = selector(&metadata).ok_or(JWTError::NoMatchingKey)?;
+        key.verify_token(token, options)
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let ecdsa_signature = ecdsa::Signature::try_from(signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                let mut digest = hmac_sha256::Hash::new();
-                digest.update(authenticated.as_bytes());
-                self.public_key()
-                    .as_ref()
-                    .verify_digest(digest, &ecdsa_signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications, and return
+    /// its claims along with its metadata
+    fn verify_token_with_options_and_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        Token::verify_with_metadata(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token, and return its standard claims, borrowed from `payload`
+    ///
+    /// The decoded payload is stored in `payload`, that can be reused across verifications to
+    /// avoid allocations. Custom claims are ignored, as well as the `lenient_audiences` and
+    /// `rfc3339_timestamps` verification options.
+    fn verify_token_borrowed<'b>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+        payload: &'b mut Vec<u8>,
+    ) -> Result<BorrowedClaims<'b>, Error> {
+        Token::verify_borrowed(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            payload,
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token created with `sign_unencoded_detached()`, along with its payload
+    fn verify_unencoded_detached(
+        &self,
+        token: &str,
+        payload: &[u8],
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_unencoded_detached(
+            Self::jwt_alg_name(),
+            token,
+            payload,
+            &options,
+            |signing_input, signature| self.verify_signature(signing_input, signature),
         )
     }
 
+    /// Verify the signature of `signing_input`
+    fn verify_signature(&self, signing_input: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let ecdsa_signature =
+            ecdsa::Signature::try_from(signature).map_err(|_| JWTError::InvalidSignature)?;
+        let mut digest = hmac_sha256::Hash::new();
+        digest.update(signing_input);
+        self.public_key()
+            .as_ref()
+            .verify_digest(digest, &ecdsa_signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
     fn create_key_id(&mut self) -> &str {
         self.set_key_id(
             Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
@@ -122,26 +443,48 @@ pub trait ECDSAP256PublicKeyLike {
             ))
             .unwrap(),
         );
-        &self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+        self.key_id().as_deref().unwrap()
     }
 }
 
+#[derive(Clone)]
 pub struct ES256KeyPair {
     key_pair: P256KeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for ES256KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ES256KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ES256PublicKey {
     pk: P256PublicKey,
     key_id: Option<String>,
 }
 
-impl ECDSAP256KeyPairLike for ES256KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for ES256PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for ES256PublicKey {}
+
+impl ECDSAP256KeyLike for ES256KeyPair {
     fn jwt_alg_name() -> &'static str {
         "ES256"
     }
+}
 
+impl ECDSAP256KeyPairLike for ES256KeyPair {
     fn key_pair(&self) -> &P256KeyPair {
         &self.key_pair
     }
@@ -163,6 +506,40 @@ impl ES256KeyPair {
         self.key_pair.to_bytes()
     }
 
+    /// Import a key pair from a DER-encoded PKCS#8 document
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(ES256KeyPair {
+            key_pair: P256KeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    /// Import a key pair from an unencrypted PKCS#8 PEM document
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(ES256KeyPair {
+            key_pair: P256KeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(ES256KeyPair {
+            key_pair: P256KeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> ES256PublicKey {
         ES256PublicKey {
             pk: self.key_pair.public_key(),
@@ -170,6 +547,17 @@ impl ES256KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate() -> Self {
         ES256KeyPair {
             key_pair: P256KeyPair::generate(),
@@ -181,13 +569,21 @@ impl ES256KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl ECDSAP256PublicKeyLike for ES256PublicKey {
+impl ECDSAP256KeyLike for ES256PublicKey {
     fn jwt_alg_name() -> &'static str {
         "ES256"
     }
+}
 
+impl ECDSAP256PublicKeyLike for ES256PublicKey {
     fn public_key(&self) -> &P256PublicKey {
         &self.pk
     }
@@ -213,6 +609,30 @@ impl ES256PublicKey {
         self.pk.to_bytes()
     }
 
+    /// Import a public key from a DER-encoded `SubjectPublicKeyInfo` structure
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(ES256PublicKey {
+            pk: P256PublicKey::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    /// Import a public key from a PEM document
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(ES256PublicKey {
+            pk: P256PublicKey::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
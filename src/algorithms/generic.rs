@@ -0,0 +1,207 @@
+//! Traits implemented by the keys of every algorithm
+//!
+//! These traits are meant for algorithm-agnostic code, such as functions that are generic over
+//! the key type. They are not part of the prelude, as their methods have the same names as the
+//! algorithm-specific ones, that would then become ambiguous.
+//!
+//! ```rust
+//! # use jwt_simple::prelude::*;
+//! use jwt_simple::algorithms::generic::{SigningKey, VerifyingKey};
+//!
+//! fn round_trip(key_pair: &impl SigningKey, public_key: &impl VerifyingKey) -> Result<(), Error> {
+//!     let token = key_pair.sign(Claims::create(Duration::from_mins(10)))?;
+//!     public_key.verify_token::<NoCustomClaims>(&token, None)?;
+//!     Ok(())
+//! }
+//!
+//! # fn main() -> Result<(), Error> {
+//! let key_pair = Ed25519KeyPair::generate();
+//! round_trip(&key_pair, &key_pair.public_key())?;
+//! let key = HS256Key::generate();
+//! round_trip(&key, &key)?;
+//! # Ok(()) }
+//! ```
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::ec::*;
+use super::eddsa::*;
+use super::es256::*;
+use super::es256k::*;
+use super::hmac::*;
+use super::rsa::*;
+use super::VerificationKey;
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+
+/// A key that can create tokens: a key pair, or a shared key for HMAC algorithms
+pub trait SigningKey {
+    /// The JWT algorithm name
+    fn algorithm(&self) -> &'static str;
+
+    /// The key identifier, if one has been attached
+    fn key_id(&self) -> &Option<String>;
+
+    /// Sign claims, or authenticate them if the key is a shared key
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error>;
+}
+
+/// A key that can verify tokens: a public key, or a shared key for HMAC algorithms
+pub trait VerifyingKey {
+    /// The JWT algorithm name
+    fn algorithm(&self) -> &'static str;
+
+    /// The key identifier, if one has been attached
+    fn key_id(&self) -> &Option<String>;
+
+    /// Verify a token using options that can be shared across multiple verifications
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<JWTClaims<CustomClaims>, Error>;
+
+    /// Verify a token
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+}
+
+macro_rules! signing_keys {
+    ($($key_type:ty: $trait_name:ident::$sign:ident,)*) => {
+        $(
+            impl SigningKey for $key_type {
+                fn algorithm(&self) -> &'static str {
+                    <$key_type>::jwt_alg_name()
+                }
+
+                fn key_id(&self) -> &Option<String> {
+                    <$key_type as $trait_name>::key_id(self)
+                }
+
+                fn sign<CustomClaims: Serialize + DeserializeOwned>(
+                    &self,
+                    claims: JWTClaims<CustomClaims>,
+                ) -> Result<String, Error> {
+                    <$key_type as $trait_name>::$sign(self, claims)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! verifying_keys {
+    ($($key_type:ty: $trait_name:ident,)*) => {
+        $(
+            impl VerifyingKey for $key_type {
+                fn algorithm(&self) -> &'static str {
+                    <$key_type>::jwt_alg_name()
+                }
+
+                fn key_id(&self) -> &Option<String> {
+                    <$key_type as $trait_name>::key_id(self)
+                }
+
+                fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+                    &self,
+                    token: &str,
+                    options: &VerificationOptions,
+                ) -> Result<JWTClaims<CustomClaims>, Error> {
+                    <$key_type as $trait_name>::verify_token_with_options(self, token, options)
+                }
+            }
+        )*
+    };
+}
+
+signing_keys! {
+    HS256Key: MACLike::authenticate,
+    HS384Key: MACLike::authenticate,
+    HS512Key: MACLike::authenticate,
+    RS256KeyPair: RSAKeyPairLike::sign,
+    RS384KeyPair: RSAKeyPairLike::sign,
+    RS512KeyPair: RSAKeyPairLike::sign,
+    PS256KeyPair: RSAKeyPairLike::sign,
+    PS384KeyPair: RSAKeyPairLike::sign,
+    PS512KeyPair: RSAKeyPairLike::sign,
+    ES256KeyPair: ECDSAP256KeyPairLike::sign,
+    ES256kKeyPair: ECDSAP256kKeyPairLike::sign,
+    Ed25519KeyPair: EdDSAKeyPairLike::sign,
+}
+
+verifying_keys! {
+    HS256Key: MACLike,
+    HS384Key: MACLike,
+    HS512Key: MACLike,
+    RS256PublicKey: RSAPublicKeyLike,
+    RS384PublicKey: RSAPublicKeyLike,
+    RS512PublicKey: RSAPublicKeyLike,
+    PS256PublicKey: RSAPublicKeyLike,
+    PS384PublicKey: RSAPublicKeyLike,
+    PS512PublicKey: RSAPublicKeyLike,
+    ES256PublicKey: ECDSAP256PublicKeyLike,
+    ES256kPublicKey: ECDSAP256kPublicKeyLike,
+    Ed25519PublicKey: EdDSAPublicKeyLike,
+}
+
+impl SigningKey for ECDSAKeyPair {
+    fn algorithm(&self) -> &'static str {
+        ECDSAKeyPair::algorithm(self)
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        ECDSAKeyPair::key_id(self)
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        ECDSAKeyPair::sign(self, claims)
+    }
+}
+
+impl VerifyingKey for ECDSAPublicKey {
+    fn algorithm(&self) -> &'static str {
+        ECDSAPublicKey::algorithm(self)
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        ECDSAPublicKey::key_id(self)
+    }
+
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        ECDSAPublicKey::verify_token_with_options(self, token, options)
+    }
+}
+
+impl VerifyingKey for VerificationKey<'_> {
+    fn algorithm(&self) -> &'static str {
+        VerificationKey::algorithm(self)
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        VerificationKey::key_id(self)
+    }
+
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        VerificationKey::verify_token_with_options(self, token, options)
+    }
+}
@@ -2,6 +2,7 @@ use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
 use hmac_sha512::sha384 as hmac_sha384;
 use rand::RngCore;
 use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
 use zeroize::Zeroize;
 
 use crate::claims::*;
@@ -11,9 +12,17 @@ use crate::jwt_header::*;
 use crate::token::*;
 
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct HMACKey(Vec<u8>);
 
+impl fmt::Debug for HMACKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("HMACKey")
+            .field(&format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl Drop for HMACKey {
     fn drop(&mut self) {
         self.0.zeroize();
@@ -30,7 +39,11 @@ impl HMACKey {
     }
 
     pub fn generate() -> Self {
-        let mut raw_key = vec![0u8; 32];
+        Self::generate_with_length(32)
+    }
+
+    pub fn generate_with_length(key_len: usize) -> Self {
+        let mut raw_key = vec![0u8; key_len];
         rand::thread_rng().fill_bytes(&mut raw_key);
         HMACKey(raw_key)
     }
@@ -48,34 +61,279 @@ pub trait MACLike {
     fn key(&self) -> &HMACKey;
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8>;
+    fn authentication_tag_bytes(&self, authenticated: &[u8]) -> Vec<u8>;
+
+    /// Compute the authentication tag of `authenticated`
+    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
+        self.authentication_tag_bytes(authenticated.as_bytes())
+    }
+
+    /// The JWT algorithm name
+    fn algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    /// Size of the hash function output, in bits
+    fn hash_bits(&self) -> usize;
+
+    /// Size of the key, in bits
+    ///
+    /// This is the length of the shared secret.
+    fn key_strength_bits(&self) -> usize {
+        self.key().as_ref().len() * 8
+    }
 
     fn authenticate<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         claims: JWTClaims<CustomClaims>,
     ) -> Result<String, Error> {
-        let mut jwt_header = JWTHeader::default();
+        self.authenticate_returning_claims(claims)
+            .map(|(token, _claims)| token)
+    }
+
+    /// Authenticate claims, and return the token along with the claims exactly as they were serialized
+    fn authenticate_returning_claims<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<(String, JWTClaims<CustomClaims>), Error> {
+        let claims = claims.truncate_timestamps();
+        let token = self.authenticate_with_header(&claims, JWTHeader::default())?;
+        Ok((token, claims))
+    }
+
+    /// Authenticate claims, using a custom header
+    ///
+    /// The algorithm is always set to the one of the key. So is the key identifier, unless the
+    /// header already includes one.
+    fn authenticate_with_header<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: &JWTClaims<CustomClaims>,
+        mut jwt_header: JWTHeader,
+    ) -> Result<String, Error> {
         jwt_header.algorithm = Self::jwt_alg_name().to_string();
-        jwt_header.key_id = self.key_id().clone();
+        if jwt_header.key_id.is_none() {
+            jwt_header.key_id = self.key_id().clone();
+        }
         Token::build(&jwt_header, claims, |authenticated| {
             Ok(self.authentication_tag(authenticated))
         })
     }
 
+    /// Authenticate claims, and attach an application-defined footer to the header
+    ///
+    /// The footer is not encrypted, but as part of the header, it is covered by the authentication tag.
+    fn authenticate_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        footer: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            footer: Some(footer.to_string()),
+            ..Default::default()
+        };
+        self.authenticate_with_header(&claims, jwt_header)
+    }
+
+    /// Authenticate claims, and set the content type (`cty`) header member
+    ///
+    /// This is mainly useful for nested tokens, where `content_type` is set to `"JWT"`.
+    fn authenticate_with_content_type<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        content_type: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            content_type: Some(content_type.to_string()),
+            ..Default::default()
+        };
+        self.authenticate_with_header(&claims, jwt_header)
+    }
+
+    /// Authenticate an arbitrary payload, without encoding it, and detach it from the token (RFC 7797)
+    ///
+    /// The authentication tag is computed over the raw payload, with the `b64` header member set to `false`.
+    /// The payload is not included in the token: it must be transmitted separately, and
+    /// provided again to `verify_unencoded_detached()`. Only detached payloads are supported, as
+    /// the compact serialization can't carry arbitrary bytes, such as a `.` in the payload.
+    fn authenticate_unencoded_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            algorithm: Self::jwt_alg_name().to_string(),
+            key_id: self.key_id().clone(),
+            ..Default::default()
+        };
+        Token::build_unencoded_detached(jwt_header, payload, |signing_input| {
+            Ok(self.authentication_tag_bytes(signing_input))
+        })
+    }
+
     fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &str,
         options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+
+    /// Verify a token, and return its claims along with the footer, if there is one
+    fn verify_token_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, Option<String>), Error> {
+        let (claims, metadata) = self.verify_token_with_metadata(token, options)?;
+        Ok((claims, metadata.footer().map(|x| x.to_string())))
+    }
+
+    /// Verify a token, and return its claims along with its metadata
+    ///
+    /// The metadata includes the header members, the length of the token, the number of claims
+    /// and the algorithm the token was verified with.
+    fn verify_token_with_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options_and_metadata(token, &options)
+    }
+
+    /// Verify a token, using a key chosen according to the token metadata
+    ///
+    /// `selector` is given the unverified metadata of the token, such as the key identifier
+    /// and the issuer, and returns the key to verify the token with, if there is one.
+    fn verify_token_with_key_selector<'a, CustomClaims: Serialize + DeserializeOwned>(
+        token: &str,
+        options: Option<VerificationOptions>,
+        selector: impl FnOnce(&TokenMetadata) -> Option<&'a Self>,
+    ) -> Result<JWTClaims<CustomClaims>, Error>
+    where
+        Self: Sized + 'a,
+    {
+        let metadata = Token::decode_metadata(token)?;
+        let key = selector(&metadata).ok_or(JWTError::NoMatchingKey)?;
+        key.verify_token(token, options)
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
             |authenticated, authentication_tag| {
-                ensure!(
-                    timingsafe_eq(&self.authentication_tag(authenticated), authentication_tag),
-                    JWTError::InvalidAuthenticationTag
-                );
+                self.verify_authentication_tag(authenticated.as_bytes(), authentication_tag)
+            },
+        )
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications, and return
+    /// its claims along with its metadata
+    fn verify_token_with_options_and_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        Token::verify_with_metadata(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, authentication_tag| {
+                self.verify_authentication_tag(authenticated.as_bytes(), authentication_tag)
+            },
+        )
+    }
+
+    /// Verify a token, and return its standard claims, borrowed from `payload`
+    ///
+    /// The decoded payload is stored in `payload`, that can be reused across verifications to
+    /// avoid allocations. Custom claims are ignored, as well as the `lenient_audiences` and
+    /// `rfc3339_timestamps` verification options.
+    fn verify_token_borrowed<'b>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+        payload: &'b mut Vec<u8>,
+    ) -> Result<BorrowedClaims<'b>, Error> {
+        Token::verify_borrowed(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            payload,
+            |authenticated, authentication_tag| {
+                self.verify_authentication_tag(authenticated.as_bytes(), authentication_tag)
+            },
+        )
+    }
+
+    /// Verify a token created with `authenticate_unencoded_detached()`, along with its payload
+    fn verify_unencoded_detached(
+        &self,
+        token: &str,
+        payload: &[u8],
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_unencoded_detached(
+            Self::jwt_alg_name(),
+            token,
+            payload,
+            &options,
+            |signing_input, authentication_tag| {
+                self.verify_authentication_tag(signing_input, authentication_tag)
+            },
+        )
+    }
+
+    /// Verify the authentication tag of `signing_input`
+    fn verify_authentication_tag(
+        &self,
+        signing_input: &[u8],
+        authentication_tag: &[u8],
+    ) -> Result<(), Error> {
+        ensure!(
+            timingsafe_eq(
+                &self.authentication_tag_bytes(signing_input),
+                authentication_tag
+            ),
+            JWTError::InvalidAuthenticationTag
+        );
+        Ok(())
+    }
+
+    /// Verify a token using any key out of a set, for example during secret rotation
+    ///
+    /// All the keys are tried, even after a match, and each tag is compared in constant time, so
+    /// that the verification time doesn't reveal which key was used, nor how close a forged tag
+    /// is to a valid one. An attacker measuring response times can thus only learn the number
+    /// of keys, not which of them authenticated a token.
+    ///
+    /// The tradeoff is that verification always costs one MAC computation per key, even for valid
+    /// tokens matching the first key. Key sets should thus be kept small, typically two keys
+    /// during a rotation.
+    fn verify_token_with_any_key<CustomClaims: Serialize + DeserializeOwned>(
+        keys: &[Self],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error>
+    where
+        Self: Sized,
+    {
+        let options = options.unwrap_or_default();
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            &options,
+            |authenticated, authentication_tag| {
+                let matched = keys.iter().fold(false, |matched, key| {
+                    let expected_tag = key.authentication_tag(authenticated);
+                    matched | timingsafe_eq(&expected_tag, authentication_tag)
+                });
+                ensure!(matched, JWTError::InvalidAuthenticationTag);
                 Ok(())
             },
         )
@@ -88,21 +346,35 @@ pub trait MACLike {
             ))
             .unwrap(),
         );
-        &self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+        self.key_id().as_deref().unwrap()
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct HS256Key {
     key: HMACKey,
     key_id: Option<String>,
 }
 
+impl fmt::Debug for HS256Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HS256Key")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl MACLike for HS256Key {
     fn jwt_alg_name() -> &'static str {
         "HS256"
     }
 
+    fn hash_bits(&self) -> usize {
+        256
+    }
+
     fn key(&self) -> &HMACKey {
         &self.key
     }
@@ -115,12 +387,16 @@ impl MACLike for HS256Key {
         self.key_id = Some(key_id);
     }
 
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
-        hmac_sha256::HMAC::mac(authenticated.as_bytes(), self.key().as_ref()).to_vec()
+    fn authentication_tag_bytes(&self, authenticated: &[u8]) -> Vec<u8> {
+        hmac_sha256::HMAC::mac(authenticated, self.key().as_ref()).to_vec()
     }
 }
 
 impl HS256Key {
+    /// Create a key from raw bytes
+    ///
+    /// The key length is not checked. This is only meant to support legacy keys, that can be
+    /// shorter than the 256 bits required by the specification. Prefer `from_bytes_checked()`.
     pub fn from_bytes(raw_key: &[u8]) -> Self {
         HS256Key {
             key: HMACKey::from_bytes(raw_key),
@@ -128,34 +404,66 @@ impl HS256Key {
         }
     }
 
+    /// Create a key from raw bytes, rejecting keys shorter than 32 bytes (256 bits)
+    pub fn from_bytes_checked(raw_key: &[u8]) -> Result<Self, Error> {
+        ensure!(raw_key.len() >= 32, JWTError::KeyTooShort);
+        Ok(Self::from_bytes(raw_key))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key.to_bytes()
     }
 
+    /// Generate a random key of 32 bytes (256 bits), the output size of the hash function
+    ///
+    /// Longer keys don't make the MAC stronger than the hash function, but may be mandated by policies.
     pub fn generate() -> Self {
         HS256Key {
-            key: HMACKey::generate(),
+            key: HMACKey::generate_with_length(32),
             key_id: None,
         }
     }
 
+    /// Generate a random key of `key_len` bytes, that must be at least 32 bytes (256 bits)
+    pub fn generate_with_length(key_len: usize) -> Result<Self, Error> {
+        ensure!(key_len >= 32, JWTError::KeyTooShort);
+        Ok(HS256Key {
+            key: HMACKey::generate_with_length(key_len),
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct HS512Key {
     key: HMACKey,
     key_id: Option<String>,
 }
 
+impl fmt::Debug for HS512Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HS512Key")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl MACLike for HS512Key {
     fn jwt_alg_name() -> &'static str {
         "HS512"
     }
 
+    fn hash_bits(&self) -> usize {
+        512
+    }
+
     fn key(&self) -> &HMACKey {
         &self.key
     }
@@ -168,12 +476,16 @@ impl MACLike for HS512Key {
         self.key_id = Some(key_id);
     }
 
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
-        hmac_sha512::HMAC::mac(authenticated.as_bytes(), self.key().as_ref()).to_vec()
+    fn authentication_tag_bytes(&self, authenticated: &[u8]) -> Vec<u8> {
+        hmac_sha512::HMAC::mac(authenticated, self.key().as_ref()).to_vec()
     }
 }
 
 impl HS512Key {
+    /// Create a key from raw bytes
+    ///
+    /// The key length is not checked. This is only meant to support legacy keys, that can be
+    /// shorter than the 512 bits required by the specification. Prefer `from_bytes_checked()`.
     pub fn from_bytes(raw_key: &[u8]) -> Self {
         HS512Key {
             key: HMACKey::from_bytes(raw_key),
@@ -181,34 +493,66 @@ impl HS512Key {
         }
     }
 
+    /// Create a key from raw bytes, rejecting keys shorter than 64 bytes (512 bits)
+    pub fn from_bytes_checked(raw_key: &[u8]) -> Result<Self, Error> {
+        ensure!(raw_key.len() >= 64, JWTError::KeyTooShort);
+        Ok(Self::from_bytes(raw_key))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key.to_bytes()
     }
 
+    /// Generate a random key of 64 bytes (512 bits), the output size of the hash function
+    ///
+    /// Longer keys don't make the MAC stronger than the hash function, but may be mandated by policies.
     pub fn generate() -> Self {
         HS512Key {
-            key: HMACKey::generate(),
+            key: HMACKey::generate_with_length(64),
             key_id: None,
         }
     }
 
+    /// Generate a random key of `key_len` bytes, that must be at least 64 bytes (512 bits)
+    pub fn generate_with_length(key_len: usize) -> Result<Self, Error> {
+        ensure!(key_len >= 64, JWTError::KeyTooShort);
+        Ok(HS512Key {
+            key: HMACKey::generate_with_length(key_len),
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct HS384Key {
     key: HMACKey,
     key_id: Option<String>,
 }
 
+impl fmt::Debug for HS384Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HS384Key")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl MACLike for HS384Key {
     fn jwt_alg_name() -> &'static str {
         "HS384"
     }
 
+    fn hash_bits(&self) -> usize {
+        384
+    }
+
     fn key(&self) -> &HMACKey {
         &self.key
     }
@@ -221,12 +565,16 @@ impl MACLike for HS384Key {
         self.key_id = Some(key_id);
     }
 
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
-        hmac_sha384::HMAC::mac(authenticated.as_bytes(), self.key().as_ref()).to_vec()
+    fn authentication_tag_bytes(&self, authenticated: &[u8]) -> Vec<u8> {
+        hmac_sha384::HMAC::mac(authenticated, self.key().as_ref()).to_vec()
     }
 }
 
 impl HS384Key {
+    /// Create a key from raw bytes
+    ///
+    /// The key length is not checked. This is only meant to support legacy keys, that can be
+    /// shorter than the 384 bits required by the specification. Prefer `from_bytes_checked()`.
     pub fn from_bytes(raw_key: &[u8]) -> Self {
         HS384Key {
             key: HMACKey::from_bytes(raw_key),
@@ -234,17 +582,35 @@ impl HS384Key {
         }
     }
 
+    /// Create a key from raw bytes, rejecting keys shorter than 48 bytes (384 bits)
+    pub fn from_bytes_checked(raw_key: &[u8]) -> Result<Self, Error> {
+        ensure!(raw_key.len() >= 48, JWTError::KeyTooShort);
+        Ok(Self::from_bytes(raw_key))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key.to_bytes()
     }
 
+    /// Generate a random key of 48 bytes (384 bits), the output size of the hash function
+    ///
+    /// Longer keys don't make the MAC stronger than the hash function, but may be mandated by policies.
     pub fn generate() -> Self {
         HS384Key {
-            key: HMACKey::generate(),
+            key: HMACKey::generate_with_length(48),
             key_id: None,
         }
     }
 
+    /// Generate a random key of `key_len` bytes, that must be at least 48 bytes (384 bits)
+    pub fn generate_with_length(key_len: usize) -> Result<Self, Error> {
+        ensure!(key_len >= 48, JWTError::KeyTooShort);
+        Ok(HS384Key {
+            key: HMACKey::generate_with_length(key_len),
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -1,9 +1,15 @@
+mod any;
+mod ec;
 mod eddsa;
 mod es256;
 mod es256k;
 mod hmac;
 mod rsa;
 
+pub mod generic;
+
+pub use self::any::*;
+pub use self::ec::*;
 pub use self::eddsa::*;
 pub use self::es256::*;
 pub use self::es256k::*;
@@ -1,16 +1,71 @@
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
 use hmac_sha512::sha384 as hmac_sha384;
-use rsa::{BigUint, PublicKey as _};
+use rsa::{BigUint, PublicKey as _, PublicKeyParts as _};
 use serde::{de::DeserializeOwned, Serialize};
 use std::convert::TryFrom;
+use std::fmt;
+use zeroize::Zeroize;
 
 use crate::claims::*;
 use crate::common::*;
 use crate::error::*;
 use crate::jwt_header::*;
+use crate::pkcs8;
 use crate::token::*;
 
+/// Return `true` if the length of the outer DER sequence exceeds the available data
+fn der_is_truncated(der: &[u8]) -> bool {
+    if der.len() < 2 {
+        return true;
+    }
+    if der[0] != 0x30 {
+        return false;
+    }
+    let (header_len, content_len) = match der[1] {
+        len @ 0..=0x7f => (2, len as usize),
+        0x80..=0x84 => {
+            let len_len = (der[1] & 0x7f) as usize;
+            if der.len() < 2 + len_len {
+                return true;
+            }
+            let content_len = der[2..2 + len_len]
+                .iter()
+                .fold(0usize, |len, &x| (len << 8) | x as usize);
+            (2 + len_len, content_len)
+        }
+        _ => return false,
+    };
+    der.len() < header_len + content_len
+}
+
+fn der_import_error_reason(der: &[u8]) -> KeyImportErrorReason {
+    if der_is_truncated(der) {
+        KeyImportErrorReason::Truncated
+    } else {
+        KeyImportErrorReason::Invalid
+    }
+}
+
+/// Check that a PKCS#8 or SPKI document is for an RSA key
+fn check_pkcs8_algorithm(der: &[u8]) -> Result<(), KeyImportErrorReason> {
+    pkcs8::check_rsa_algorithm(der).map_err(|reason| match reason {
+        KeyImportErrorReason::Invalid => der_import_error_reason(der),
+        reason => reason,
+    })
+}
+
+fn parse_pem(pem: &str) -> Result<rsa::pem::Pem, KeyImportErrorReason> {
+    let parsed_pem = pkcs8::parse_pem(pem)?;
+    match parsed_pem.tag.as_str() {
+        "RSA PRIVATE KEY" | "RSA PUBLIC KEY" => {}
+        "PRIVATE KEY" | "PUBLIC KEY" => check_pkcs8_algorithm(&parsed_pem.contents)?,
+        _ => return Err(KeyImportErrorReason::WrongAlgorithm),
+    }
+    Ok(parsed_pem)
+}
+
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RSAPublicKey(rsa::RSAPublicKey);
 
 impl AsRef<rsa::RSAPublicKey> for RSAPublicKey {
@@ -21,28 +76,72 @@ impl AsRef<rsa::RSAPublicKey> for RSAPublicKey {
 
 impl RSAPublicKey {
     pub fn from_der(der: &[u8]) -> Result<Self, Error> {
-        let rsa_pk = rsa::RSAPublicKey::from_pkcs8(der)?;
+        check_pkcs8_algorithm(der).map_err(KeyImportErrorReason::public_key_error)?;
+        let rsa_pk = rsa::RSAPublicKey::from_pkcs8(der)
+            .map_err(|_| der_import_error_reason(der).public_key_error())?;
         Ok(RSAPublicKey(rsa_pk))
     }
 
     pub fn from_pem(pem: &str) -> Result<Self, Error> {
-        let parsed_pem = rsa::pem::parse(pem)?;
-        let rsa_pk = rsa::RSAPublicKey::try_from(parsed_pem)?;
+        let parsed_pem = parse_pem(pem).map_err(KeyImportErrorReason::public_key_error)?;
+        let reason = der_import_error_reason(&parsed_pem.contents);
+        let rsa_pk =
+            rsa::RSAPublicKey::try_from(parsed_pem).map_err(|_| reason.public_key_error())?;
         Ok(RSAPublicKey(rsa_pk))
     }
 
+    /// Export the public key as a DER-encoded `SubjectPublicKeyInfo` structure
+    pub fn to_der(&self) -> Vec<u8> {
+        let pkcs1 = pkcs8::der_sequence(&[
+            &pkcs8::der_unsigned_integer(&self.0.n().to_bytes_be()),
+            &pkcs8::der_unsigned_integer(&self.0.e().to_bytes_be()),
+        ]);
+        pkcs8::rsa_public_key_der(&pkcs1)
+    }
+
+    pub fn to_pem(&self) -> String {
+        pkcs8::to_pem("PUBLIC KEY", self.to_der())
+    }
+
     pub fn from_components(n: &[u8], e: &[u8]) -> Result<Self, Error> {
         let n = BigUint::from_bytes_be(n);
         let e = BigUint::from_bytes_be(e);
         let rsa_pk = rsa::RSAPublicKey::new(n, e)?;
         Ok(RSAPublicKey(rsa_pk))
     }
+
+    pub fn modulus_bits(&self) -> usize {
+        self.0.n().bits()
+    }
+
+    /// RFC 7638 thumbprint of the public key
+    pub(crate) fn thumbprint(&self) -> String {
+        jwk_thumbprint(&[
+            (
+                "e",
+                &Base64UrlSafeNoPadding::encode_to_string(self.0.e().to_bytes_be()).unwrap(),
+            ),
+            ("kty", "RSA"),
+            (
+                "n",
+                &Base64UrlSafeNoPadding::encode_to_string(self.0.n().to_bytes_be()).unwrap(),
+            ),
+        ])
+    }
 }
 
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct RSAKeyPair(rsa::RSAPrivateKey);
 
+impl fmt::Debug for RSAKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RSAKeyPair")
+            .field(&format_args!("[redacted]"))
+            .finish()
+    }
+}
+
 impl AsRef<rsa::RSAPrivateKey> for RSAKeyPair {
     fn as_ref(&self) -> &rsa::RSAPrivateKey {
         &self.0
@@ -51,17 +150,74 @@ impl AsRef<rsa::RSAPrivateKey> for RSAKeyPair {
 
 impl RSAKeyPair {
     pub fn from_der(der: &[u8]) -> Result<Self, Error> {
-        let mut rsa_sk = rsa::RSAPrivateKey::from_pkcs8(&der)?;
-        rsa_sk.validate()?;
-        rsa_sk.precompute()?;
-        Ok(RSAKeyPair(rsa_sk))
+        check_pkcs8_algorithm(der).map_err(KeyImportErrorReason::key_pair_error)?;
+        let rsa_sk = rsa::RSAPrivateKey::from_pkcs8(der)
+            .map_err(|_| der_import_error_reason(der).key_pair_error())?;
+        Self::from_rsa_private_key(rsa_sk)
     }
 
     pub fn from_pem(pem: &str) -> Result<Self, Error> {
-        let parsed_pem = rsa::pem::parse(pem)?;
-        let mut rsa_sk = rsa::RSAPrivateKey::try_from(parsed_pem)?;
-        rsa_sk.validate()?;
-        rsa_sk.precompute()?;
+        let parsed_pem = parse_pem(pem).map_err(KeyImportErrorReason::key_pair_error)?;
+        let reason = der_import_error_reason(&parsed_pem.contents);
+        let rsa_sk =
+            rsa::RSAPrivateKey::try_from(parsed_pem).map_err(|_| reason.key_pair_error())?;
+        Self::from_rsa_private_key(rsa_sk)
+    }
+
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        let mut der =
+            pkcs8::decrypt_pem(pem, passphrase).map_err(KeyImportErrorReason::key_pair_error)?;
+        let key_pair = Self::from_der(&der);
+        der.zeroize();
+        key_pair
+    }
+
+    /// Export the key pair as a DER-encoded PKCS#8 private key
+    pub fn to_der(&self) -> Vec<u8> {
+        let sk = &self.0;
+        let one = BigUint::from(1u32);
+        let two = BigUint::from(2u32);
+        // Only two-prime keys can be imported or generated
+        let (p, q) = (&sk.primes()[0], &sk.primes()[1]);
+        let mut fields: Vec<Vec<u8>> = [
+            BigUint::from(0u32),
+            sk.n().clone(),
+            sk.e().clone(),
+            sk.d().clone(),
+            p.clone(),
+            q.clone(),
+            sk.d() % (p - &one),
+            sk.d() % (q - &one),
+            // q^-1 mod p, using Fermat's little theorem
+            q.modpow(&(p - &two), p),
+        ]
+        .iter()
+        .map(|x| {
+            let mut be = x.to_bytes_be();
+            let der = pkcs8::der_unsigned_integer(&be);
+            be.zeroize();
+            der
+        })
+        .collect();
+        let fields_refs: Vec<&[u8]> = fields.iter().map(|x| x.as_slice()).collect();
+        let mut pkcs1 = pkcs8::der_sequence(&fields_refs);
+        fields.iter_mut().for_each(|x| x.zeroize());
+        let der = pkcs8::rsa_private_key_der(&pkcs1);
+        pkcs1.zeroize();
+        der
+    }
+
+    pub fn to_pem(&self) -> String {
+        pkcs8::to_pem("PRIVATE KEY", self.to_der())
+    }
+
+    fn from_rsa_private_key(mut rsa_sk: rsa::RSAPrivateKey) -> Result<Self, Error> {
+        rsa_sk
+            .validate()
+            .map_err(|_| KeyImportErrorReason::Invalid.key_pair_error())?;
+        rsa_sk
+            .precompute()
+            .map_err(|_| KeyImportErrorReason::Invalid.key_pair_error())?;
         Ok(RSAKeyPair(rsa_sk))
     }
 
@@ -70,6 +226,10 @@ impl RSAKeyPair {
         RSAPublicKey(rsa_pk)
     }
 
+    pub fn modulus_bits(&self) -> usize {
+        self.0.n().bits()
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         match modulus_bits {
             2048 | 3072 | 4096 => {}
@@ -81,79 +241,312 @@ impl RSAKeyPair {
     }
 }
 
+/// Properties shared by RSA key pairs and public keys
 #[doc(hidden)]
-pub trait RSAKeyPairLike {
+pub trait RSAKeyLike {
     fn jwt_alg_name() -> &'static str;
+
+    /// The JWT algorithm name
+    fn algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    /// Size of the hash function output, in bits
+    fn hash_bits(&self) -> usize;
+}
+
+#[doc(hidden)]
+pub trait RSAKeyPairLike: RSAKeyLike {
     fn key_pair(&self) -> &RSAKeyPair;
     fn key_id(&self) -> &Option<String>;
     fn hash(message: &[u8]) -> Vec<u8>;
     fn padding_scheme(&self) -> rsa::PaddingScheme;
 
+    /// Size of the key, in bits
+    ///
+    /// This is the size of the RSA modulus.
+    fn key_strength_bits(&self) -> usize {
+        self.key_pair().modulus_bits()
+    }
+
     fn sign<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         claims: JWTClaims<CustomClaims>,
     ) -> Result<String, Error> {
-        let mut jwt_header = JWTHeader::default();
+        self.sign_returning_claims(claims)
+            .map(|(token, _claims)| token)
+    }
+
+    /// Sign claims, and return the token along with the claims exactly as they were serialized
+    fn sign_returning_claims<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<(String, JWTClaims<CustomClaims>), Error> {
+        let claims = claims.truncate_timestamps();
+        let token = self.sign_with_header(&claims, JWTHeader::default())?;
+        Ok((token, claims))
+    }
+
+    /// Sign claims, using a custom header
+    ///
+    /// The algorithm is always set to the one of the key. So is the key identifier, unless the
+    /// header already includes one.
+    fn sign_with_header<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: &JWTClaims<CustomClaims>,
+        mut jwt_header: JWTHeader,
+    ) -> Result<String, Error> {
         jwt_header.algorithm = Self::jwt_alg_name().to_string();
-        jwt_header.key_id = self.key_id().clone();
+        if jwt_header.key_id.is_none() {
+            jwt_header.key_id = self.key_id().clone();
+        }
         Token::build(&jwt_header, claims, |authenticated| {
-            let digest = Self::hash(authenticated.as_bytes());
-            let mut rng = rand::thread_rng();
-            let token =
-                self.key_pair()
-                    .as_ref()
-                    .sign_blinded(&mut rng, self.padding_scheme(), &digest)?;
-            Ok(token)
+            self.signature(authenticated.as_bytes())
         })
     }
+
+    /// Sign claims, and attach an application-defined footer to the header
+    ///
+    /// The footer is not encrypted, but as part of the header, it is covered by the signature.
+    fn sign_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        footer: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            footer: Some(footer.to_string()),
+            ..Default::default()
+        };
+        self.sign_with_header(&claims, jwt_header)
+    }
+
+    /// Sign claims, and set the content type (`cty`) header member
+    ///
+    /// This is mainly useful for nested tokens, where `content_type` is set to `"JWT"`.
+    fn sign_with_content_type<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        content_type: &str,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            content_type: Some(content_type.to_string()),
+            ..Default::default()
+        };
+        self.sign_with_header(&claims, jwt_header)
+    }
+
+    /// Sign an arbitrary payload, without encoding it, and detach it from the token (RFC 7797)
+    ///
+    /// The signature is computed over the raw payload, with the `b64` header member set to `false`.
+    /// The payload is not included in the token: it must be transmitted separately, and
+    /// provided again to `verify_unencoded_detached()`. Only detached payloads are supported, as
+    /// the compact serialization can't carry arbitrary bytes, such as a `.` in the payload.
+    fn sign_unencoded_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader {
+            algorithm: Self::jwt_alg_name().to_string(),
+            key_id: self.key_id().clone(),
+            ..Default::default()
+        };
+        Token::build_unencoded_detached(jwt_header, payload, |signing_input| {
+            self.signature(signing_input)
+        })
+    }
+
+    /// Compute the signature of `signing_input`
+    fn signature(&self, signing_input: &[u8]) -> Result<Vec<u8>, Error> {
+        let digest = Self::hash(signing_input);
+        let mut rng = rand::thread_rng();
+        let signature =
+            self.key_pair()
+                .as_ref()
+                .sign_blinded(&mut rng, self.padding_scheme(), &digest)?;
+        Ok(signature)
+    }
 }
 
 #[doc(hidden)]
-pub trait RSAPublicKeyLike {
-    fn jwt_alg_name() -> &'static str;
+pub trait RSAPublicKeyLike: RSAKeyLike {
     fn public_key(&self) -> &RSAPublicKey;
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
     fn hash(message: &[u8]) -> Vec<u8>;
     fn padding_scheme(&self) -> rsa::PaddingScheme;
 
+    /// Size of the key, in bits
+    ///
+    /// This is the size of the RSA modulus.
+    fn key_strength_bits(&self) -> usize {
+        self.public_key().modulus_bits()
+    }
+
     fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &str,
         options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options(token, &options)
+    }
+
+    /// Verify a token, and return its claims along with the footer, if there is one
+    fn verify_token_with_footer<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, Option<String>), Error> {
+        let (claims, metadata) = self.verify_token_with_metadata(token, options)?;
+        Ok((claims, metadata.footer().map(|x| x.to_string())))
+    }
+
+    /// Verify a token, and return its claims along with its metadata
+    ///
+    /// The metadata includes the header members, the length of the token, the number of claims
+    /// and the algorithm the token was verified with.
+    fn verify_token_with_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        let options = options.unwrap_or_default();
+        self.verify_token_with_options_and_metadata(token, &options)
+    }
+
+    /// Verify a token, using a key chosen according to the token metadata
+    ///
+    /// `selector` is given the unverified metadata of the token, such as the key identifier
+    /// and the issuer, and returns the key to verify the token with, if there is one.
+    fn verify_token_with_key_selector<'a, CustomClaims: Serialize + DeserializeOwned>(
+        token: &str,
+        options: Option<VerificationOptions>,
+        selector: impl FnOnce(&TokenMetadata) -> Option<&'a Self>,
+    ) -> Result<JWTClaims<CustomClaims>, Error>
+    where
+        Self: Sized + 'a,
+    {
+        let metadata = Token::decode_metadata(token)?;
+        let key = selector(&metadata).ok_or(JWTError::NoMatchingKey)?;
+        key.verify_token(token, options)
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications
+    fn verify_token_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let digest = Self::hash(authenticated.as_bytes());
-                self.public_key()
-                    .as_ref()
-                    .verify(self.padding_scheme(), &digest, signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token using options that can be shared across multiple verifications, and return
+    /// its claims along with its metadata
+    fn verify_token_with_options_and_metadata<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error> {
+        Token::verify_with_metadata(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token, and return its standard claims, borrowed from `payload`
+    ///
+    /// The decoded payload is stored in `payload`, that can be reused across verifications to
+    /// avoid allocations. Custom claims are ignored, as well as the `lenient_audiences` and
+    /// `rfc3339_timestamps` verification options.
+    fn verify_token_borrowed<'b>(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+        payload: &'b mut Vec<u8>,
+    ) -> Result<BorrowedClaims<'b>, Error> {
+        Token::verify_borrowed(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            payload,
+            |authenticated, signature| self.verify_signature(authenticated.as_bytes(), signature),
+        )
+    }
+
+    /// Verify a token created with `sign_unencoded_detached()`, along with its payload
+    fn verify_unencoded_detached(
+        &self,
+        token: &str,
+        payload: &[u8],
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_unencoded_detached(
+            Self::jwt_alg_name(),
+            token,
+            payload,
+            &options,
+            |signing_input, signature| self.verify_signature(signing_input, signature),
         )
     }
+
+    /// Verify the signature of `signing_input`
+    fn verify_signature(&self, signing_input: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let digest = Self::hash(signing_input);
+        self.public_key()
+            .as_ref()
+            .verify(self.padding_scheme(), &digest, signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
 }
 
+#[derive(Clone)]
 pub struct RS256KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for RS256KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RS256KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RS256PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
 }
 
-impl RSAKeyPairLike for RS256KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for RS256PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for RS256PublicKey {}
+
+impl RSAKeyLike for RS256KeyPair {
     fn jwt_alg_name() -> &'static str {
         "RS256"
     }
 
+    fn hash_bits(&self) -> usize {
+        256
+    }
+}
+
+impl RSAKeyPairLike for RS256KeyPair {
     fn key_pair(&self) -> &RSAKeyPair {
         &self.key_pair
     }
@@ -186,6 +579,36 @@ impl RS256KeyPair {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the key pair using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(RS256KeyPair {
+            key_pair: RSAKeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> RS256PublicKey {
         RS256PublicKey {
             pk: self.key_pair.public_key(),
@@ -193,6 +616,17 @@ impl RS256KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         Ok(RS256KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
@@ -204,13 +638,25 @@ impl RS256KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl RSAPublicKeyLike for RS256PublicKey {
+impl RSAKeyLike for RS256PublicKey {
     fn jwt_alg_name() -> &'static str {
         "RS256"
     }
 
+    fn hash_bits(&self) -> usize {
+        256
+    }
+}
+
+impl RSAPublicKeyLike for RS256PublicKey {
     fn hash(message: &[u8]) -> Vec<u8> {
         hmac_sha256::Hash::hash(message).to_vec()
     }
@@ -247,6 +693,26 @@ impl RS256PublicKey {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the public key using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -255,23 +721,48 @@ impl RS256PublicKey {
 
 //
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct RS512KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for RS512KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RS512KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RS512PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
 }
 
-impl RSAKeyPairLike for RS512KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for RS512PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for RS512PublicKey {}
+
+impl RSAKeyLike for RS512KeyPair {
     fn jwt_alg_name() -> &'static str {
         "RS512"
     }
 
+    fn hash_bits(&self) -> usize {
+        512
+    }
+}
+
+impl RSAKeyPairLike for RS512KeyPair {
     fn key_pair(&self) -> &RSAKeyPair {
         &self.key_pair
     }
@@ -304,6 +795,36 @@ impl RS512KeyPair {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the key pair using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(RS512KeyPair {
+            key_pair: RSAKeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> RS512PublicKey {
         RS512PublicKey {
             pk: self.key_pair.public_key(),
@@ -311,6 +832,17 @@ impl RS512KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         Ok(RS512KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
@@ -322,13 +854,25 @@ impl RS512KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl RSAPublicKeyLike for RS512PublicKey {
+impl RSAKeyLike for RS512PublicKey {
     fn jwt_alg_name() -> &'static str {
         "RS512"
     }
 
+    fn hash_bits(&self) -> usize {
+        512
+    }
+}
+
+impl RSAPublicKeyLike for RS512PublicKey {
     fn hash(message: &[u8]) -> Vec<u8> {
         hmac_sha512::Hash::hash(message).to_vec()
     }
@@ -365,6 +909,26 @@ impl RS512PublicKey {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the public key using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -373,23 +937,48 @@ impl RS512PublicKey {
 
 //
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct RS384KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for RS384KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RS384KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RS384PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
 }
 
-impl RSAKeyPairLike for RS384KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for RS384PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for RS384PublicKey {}
+
+impl RSAKeyLike for RS384KeyPair {
     fn jwt_alg_name() -> &'static str {
         "RS384"
     }
 
+    fn hash_bits(&self) -> usize {
+        384
+    }
+}
+
+impl RSAKeyPairLike for RS384KeyPair {
     fn key_pair(&self) -> &RSAKeyPair {
         &self.key_pair
     }
@@ -422,6 +1011,36 @@ impl RS384KeyPair {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the key pair using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(RS384KeyPair {
+            key_pair: RSAKeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> RS384PublicKey {
         RS384PublicKey {
             pk: self.key_pair.public_key(),
@@ -429,6 +1048,17 @@ impl RS384KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         Ok(RS384KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
@@ -440,13 +1070,25 @@ impl RS384KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl RSAPublicKeyLike for RS384PublicKey {
+impl RSAKeyLike for RS384PublicKey {
     fn jwt_alg_name() -> &'static str {
         "RS384"
     }
 
+    fn hash_bits(&self) -> usize {
+        384
+    }
+}
+
+impl RSAPublicKeyLike for RS384PublicKey {
     fn hash(message: &[u8]) -> Vec<u8> {
         hmac_sha384::Hash::hash(message).to_vec()
     }
@@ -483,6 +1125,26 @@ impl RS384PublicKey {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the public key using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -491,22 +1153,48 @@ impl RS384PublicKey {
 
 //
 
+#[derive(Clone)]
 pub struct PS256KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for PS256KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PS256KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PS256PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
 }
 
-impl RSAKeyPairLike for PS256KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for PS256PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for PS256PublicKey {}
+
+impl RSAKeyLike for PS256KeyPair {
     fn jwt_alg_name() -> &'static str {
         "PS256"
     }
 
+    fn hash_bits(&self) -> usize {
+        256
+    }
+}
+
+impl RSAKeyPairLike for PS256KeyPair {
     fn key_pair(&self) -> &RSAKeyPair {
         &self.key_pair
     }
@@ -539,6 +1227,36 @@ impl PS256KeyPair {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the key pair using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(PS256KeyPair {
+            key_pair: RSAKeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> PS256PublicKey {
         PS256PublicKey {
             pk: self.key_pair.public_key(),
@@ -546,6 +1264,17 @@ impl PS256KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         Ok(PS256KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
@@ -557,13 +1286,25 @@ impl PS256KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl RSAPublicKeyLike for PS256PublicKey {
+impl RSAKeyLike for PS256PublicKey {
     fn jwt_alg_name() -> &'static str {
         "PS256"
     }
 
+    fn hash_bits(&self) -> usize {
+        256
+    }
+}
+
+impl RSAPublicKeyLike for PS256PublicKey {
     fn hash(message: &[u8]) -> Vec<u8> {
         hmac_sha256::Hash::hash(message).to_vec()
     }
@@ -600,6 +1341,26 @@ impl PS256PublicKey {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the public key using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -608,22 +1369,48 @@ impl PS256PublicKey {
 
 //
 
+#[derive(Clone)]
 pub struct PS512KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for PS512KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PS512KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PS512PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
 }
 
-impl RSAKeyPairLike for PS512KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for PS512PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for PS512PublicKey {}
+
+impl RSAKeyLike for PS512KeyPair {
     fn jwt_alg_name() -> &'static str {
         "PS512"
     }
 
+    fn hash_bits(&self) -> usize {
+        512
+    }
+}
+
+impl RSAKeyPairLike for PS512KeyPair {
     fn key_pair(&self) -> &RSAKeyPair {
         &self.key_pair
     }
@@ -656,6 +1443,36 @@ impl PS512KeyPair {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the key pair using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(PS512KeyPair {
+            key_pair: RSAKeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> PS512PublicKey {
         PS512PublicKey {
             pk: self.key_pair.public_key(),
@@ -663,6 +1480,17 @@ impl PS512KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         Ok(PS512KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
@@ -674,13 +1502,25 @@ impl PS512KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl RSAPublicKeyLike for PS512PublicKey {
+impl RSAKeyLike for PS512PublicKey {
     fn jwt_alg_name() -> &'static str {
         "PS512"
     }
 
+    fn hash_bits(&self) -> usize {
+        512
+    }
+}
+
+impl RSAPublicKeyLike for PS512PublicKey {
     fn hash(message: &[u8]) -> Vec<u8> {
         hmac_sha512::Hash::hash(message).to_vec()
     }
@@ -717,6 +1557,26 @@ impl PS512PublicKey {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the public key using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -725,22 +1585,48 @@ impl PS512PublicKey {
 
 //
 
+#[derive(Clone)]
 pub struct PS384KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for PS384KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PS384KeyPair")
+            .field("algorithm", &Self::jwt_alg_name())
+            .field("key_id", &self.key_id)
+            .field("key_pair", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PS384PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
 }
 
-impl RSAKeyPairLike for PS384KeyPair {
+// Public keys are compared by key material; key identifiers are ignored
+impl PartialEq for PS384PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk == other.pk
+    }
+}
+
+impl Eq for PS384PublicKey {}
+
+impl RSAKeyLike for PS384KeyPair {
     fn jwt_alg_name() -> &'static str {
         "PS384"
     }
 
+    fn hash_bits(&self) -> usize {
+        384
+    }
+}
+
+impl RSAKeyPairLike for PS384KeyPair {
     fn key_pair(&self) -> &RSAKeyPair {
         &self.key_pair
     }
@@ -773,6 +1659,36 @@ impl PS384KeyPair {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    /// Import a key pair from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the key pair using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    /// Import a key pair from a password-protected PKCS#8 PEM document
+    ///
+    /// Only the PBES2 scheme with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+    pub fn from_encrypted_pem(pem: &str, passphrase: &[u8]) -> Result<Self, Error> {
+        Ok(PS384KeyPair {
+            key_pair: RSAKeyPair::from_encrypted_pem(pem, passphrase)?,
+            key_id: None,
+        })
+    }
+
     pub fn public_key(&self) -> PS384PublicKey {
         PS384PublicKey {
             pk: self.key_pair.public_key(),
@@ -780,6 +1696,17 @@ impl PS384KeyPair {
         }
     }
 
+    /// Verify a token using the public key of this key pair
+    ///
+    /// This is a shortcut for `key_pair.public_key().verify_token()`, mostly useful for round-trip tests.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.public_key().verify_token(token, options)
+    }
+
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
         Ok(PS384KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
@@ -791,13 +1718,25 @@ impl PS384KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Set the key identifier to the RFC 7638 thumbprint of the public key
+    pub fn with_auto_key_id(mut self) -> Self {
+        self.key_id = Some(self.key_pair.public_key().thumbprint());
+        self
+    }
 }
 
-impl RSAPublicKeyLike for PS384PublicKey {
+impl RSAKeyLike for PS384PublicKey {
     fn jwt_alg_name() -> &'static str {
         "PS384"
     }
 
+    fn hash_bits(&self) -> usize {
+        384
+    }
+}
+
+impl RSAPublicKeyLike for PS384PublicKey {
     fn hash(message: &[u8]) -> Vec<u8> {
         hmac_sha384::Hash::hash(message).to_vec()
     }
@@ -834,6 +1773,26 @@ impl PS384PublicKey {
         })
     }
 
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from its DER encoding, like `from_der()`
+    ///
+    /// RSA keys don't have a more compact representation.
+    pub fn from_bytes(der: &[u8]) -> Result<Self, Error> {
+        Self::from_der(der)
+    }
+
+    /// Export the public key using its DER encoding, like `to_der()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
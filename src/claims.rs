@@ -1,20 +1,140 @@
 use coarsetime::{Clock, Duration, UnixTimeStamp};
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
-use rand::RngCore;
+use rand::{CryptoRng, RngCore};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashSet;
 use std::convert::TryInto;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
 
-use crate::common::VerificationOptions;
+use crate::common::{RequiredClaims, ToUnixTimeStamp, TokenAgeAnchor, VerificationOptions};
 use crate::error::*;
 use crate::serde_additions;
 
+/// Time tolerance used when `VerificationOptions::time_tolerance` is not set
 pub const DEFAULT_TIME_TOLERANCE_SECS: u64 = 900;
 
+/// Names of the standard claims, as serialized
+pub(crate) const STANDARD_CLAIM_NAMES: &[&str] = &[
+    "iat",
+    "exp",
+    "nbf",
+    "iss",
+    "sub",
+    "aud",
+    "jti",
+    "nonce",
+    "at_hash",
+    "c_hash",
+    "auth_time",
+    "htm",
+    "htu",
+    "ath",
+    "amr",
+    "acr",
+];
+
+/// Alternative spellings of standard claim names, that are most likely mistakes
+const STANDARD_CLAIM_LONG_NAMES: &[&str] = &[
+    "issued_at",
+    "expires",
+    "expires_at",
+    "expiration",
+    "not_before",
+    "invalid_before",
+    "issuer",
+    "subject",
+    "audience",
+    "audiences",
+    "jwt_id",
+    "authentication_time",
+];
+
+/// Return the first claim name colliding with a standard claim it isn't, if any.
+///
+/// A name collides if it differs from a standard claim name only by case, or if it is a
+/// long form of a standard claim name, such as `expires` instead of `exp`.
+pub(crate) fn reserved_claim_collision<'t>(
+    claim_names: impl IntoIterator<Item = &'t str>,
+) -> Option<&'t str> {
+    claim_names.into_iter().find(|name| {
+        !STANDARD_CLAIM_NAMES.contains(name)
+            && STANDARD_CLAIM_NAMES
+                .iter()
+                .chain(STANDARD_CLAIM_LONG_NAMES.iter())
+                .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    })
+}
+
 /// Type representing the fact that no application-defined claims is necessary.
+///
+/// When verifying a token, application-defined members are ignored with this type. It can thus
+/// be used to only check the tag or signature and the standard claims, whatever the token contains.
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct NoCustomClaims {}
 
+/// Application-defined claims whose shape is not known in advance.
+///
+/// Standard claims are still parsed into their dedicated properties; every other
+/// member of the claims object ends up in this map.
+pub type DynamicClaims = serde_json::Map<String, serde_json::Value>;
+
+/// W3C Verifiable Credentials claims, to be used as custom claims.
+///
+/// The credential and the presentation are kept as JSON values; accessors are provided
+/// for the most common properties of the credential.
+#[cfg(feature = "vc")]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VerifiableCredentialClaims {
+    /// Verifiable credential
+    #[serde(rename = "vc", default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<serde_json::Value>,
+
+    /// Verifiable presentation
+    #[serde(rename = "vp", default, skip_serializing_if = "Option::is_none")]
+    pub presentation: Option<serde_json::Value>,
+}
+
+#[cfg(feature = "vc")]
+impl VerifiableCredentialClaims {
+    /// Claims including a verifiable credential
+    pub fn with_credential(credential: serde_json::Value) -> Self {
+        VerifiableCredentialClaims {
+            credential: Some(credential),
+            presentation: None,
+        }
+    }
+
+    /// Claims including a verifiable presentation
+    pub fn with_presentation(presentation: serde_json::Value) -> Self {
+        VerifiableCredentialClaims {
+            credential: None,
+            presentation: Some(presentation),
+        }
+    }
+
+    /// The types of the credential
+    ///
+    /// The `type` property can be either a string or an array of strings. We support both.
+    pub fn credential_types(&self) -> Vec<&str> {
+        match self.credential.as_ref().and_then(|x| x.get("type")) {
+            Some(serde_json::Value::String(credential_type)) => vec![credential_type.as_str()],
+            Some(serde_json::Value::Array(credential_types)) => {
+                credential_types.iter().filter_map(|x| x.as_str()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The subject of the credential
+    pub fn credential_subject(&self) -> Option<&serde_json::Value> {
+        self.credential
+            .as_ref()
+            .and_then(|x| x.get("credentialSubject"))
+    }
+}
+
 /// Depending on applications, the `audiences` property may be either a set or a string.
 /// We support both.
 #[derive(Debug, Eq, PartialEq)]
@@ -34,6 +154,21 @@ impl Audiences {
         matches!(self, Audiences::AsString(_))
     }
 
+    /// Return the number of audiences
+    ///
+    /// An empty string counts as no audiences, like in `into_set()`.
+    pub fn len(&self) -> usize {
+        match self {
+            Audiences::AsSet(audiences) => audiences.len(),
+            Audiences::AsString(audience) => !audience.is_empty() as usize,
+        }
+    }
+
+    /// Return `true` if there are no audiences
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Get the audiences as a set
     pub fn into_set(self) -> HashSet<String> {
         match self {
@@ -65,6 +200,21 @@ impl Audiences {
             }
         }
     }
+
+    /// Get the audiences as a string, joining multiple audiences with `delimiter`
+    ///
+    /// Unlike `into_string()`, this never fails. Audiences of a set are sorted, so that the
+    /// result is deterministic. The `audience_delimiters` verification option splits them back.
+    pub fn into_joined_string(self, delimiter: &str) -> String {
+        match self {
+            Audiences::AsString(audiences_str) => audiences_str,
+            Audiences::AsSet(audiences) => {
+                let mut audiences: Vec<String> = audiences.into_iter().collect();
+                audiences.sort();
+                audiences.join(delimiter)
+            }
+        }
+    }
 }
 
 impl TryInto<String> for Audiences {
@@ -74,9 +224,19 @@ impl TryInto<String> for Audiences {
     }
 }
 
-impl Into<HashSet<String>> for Audiences {
-    fn into(self) -> HashSet<String> {
-        self.into_set()
+impl From<Audiences> for HashSet<String> {
+    fn from(audiences: Audiences) -> HashSet<String> {
+        audiences.into_set()
+    }
+}
+
+/// Collect audiences into a set
+///
+/// `From<Vec<String>>` cannot be implemented alongside the conversion from strings;
+/// `audiences.into_iter().collect()` can be used instead.
+impl FromIterator<String> for Audiences {
+    fn from_iter<I: IntoIterator<Item = String>>(audiences: I) -> Audiences {
+        Audiences::AsSet(audiences.into_iter().collect())
     }
 }
 
@@ -150,103 +310,492 @@ pub struct JWTClaims<CustomClaims> {
     #[serde(rename = "nonce", default, skip_serializing_if = "Option::is_none")]
     pub nonce: Option<String>,
 
+    /// OpenID Connect authentication time
+    #[serde(
+        rename = "auth_time",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub auth_time: Option<UnixTimeStamp>,
+
+    /// OpenID Connect authentication methods, such as `pwd` or `mfa`
+    #[serde(rename = "amr", default, skip_serializing_if = "Option::is_none")]
+    pub authentication_methods: Option<Vec<String>>,
+
+    /// OpenID Connect authentication context class
+    #[serde(rename = "acr", default, skip_serializing_if = "Option::is_none")]
+    pub auth_context_class: Option<String>,
+
+    /// OpenID Connect access token hash
+    #[serde(rename = "at_hash", default, skip_serializing_if = "Option::is_none")]
+    pub access_token_hash: Option<String>,
+
+    /// OpenID Connect authorization code hash
+    #[serde(rename = "c_hash", default, skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<String>,
+
+    /// DPoP HTTP method of the request the proof is attached to
+    #[serde(rename = "htm", default, skip_serializing_if = "Option::is_none")]
+    pub http_method: Option<String>,
+
+    /// DPoP HTTP URI of the request the proof is attached to, without query and fragment
+    #[serde(rename = "htu", default, skip_serializing_if = "Option::is_none")]
+    pub http_uri: Option<String>,
+
+    /// DPoP access token hash
+    #[serde(rename = "ath", default, skip_serializing_if = "Option::is_none")]
+    pub dpop_access_token_hash: Option<String>,
+
+    /// Serialize time claims as RFC 3339 strings instead of numbers
+    ///
+    /// This is not compliant with the JWT specification, and should only be used for tokens
+    /// exchanged between cooperating services. See `VerificationOptions::rfc3339_timestamps`.
+    ///
+    /// This requires the `chrono` feature. Without it, signing fails with
+    /// `JWTError::RFC3339TimestampsUnsupported`.
+    #[serde(skip)]
+    pub rfc3339_timestamps: bool,
+
     /// Custom (application-defined) claims
     #[serde(flatten)]
     pub custom: CustomClaims,
 }
 
-impl<CustomClaims> JWTClaims<CustomClaims> {
-    pub(crate) fn validate(&self, options: &VerificationOptions) -> Result<(), Error> {
-        let now = Clock::now_since_epoch();
-        let time_tolerance = options
-            .time_tolerance
-            .unwrap_or_else(|| Duration::from_secs(DEFAULT_TIME_TOLERANCE_SECS));
+/// Standard claims, whose strings are borrowed from the decoded payload of a token
+///
+/// This is returned by the `verify_token_borrowed()` functions, for applications that only
+/// need to read standard claims, and want to avoid allocating a string for each of them.
+/// Custom claims are ignored. Strings containing escape sequences are still allocated.
+#[derive(Debug, Deserialize)]
+pub struct BorrowedClaims<'a> {
+    /// Time the claims were created at
+    #[serde(
+        rename = "iat",
+        default,
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub issued_at: Option<UnixTimeStamp>,
 
-        if let Some(reject_before) = options.reject_before {
-            ensure!(now <= reject_before, JWTError::OldTokenReused);
+    /// Time the claims expire at
+    #[serde(
+        rename = "exp",
+        default,
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub expires_at: Option<UnixTimeStamp>,
+
+    /// Time the claims will be invalid until
+    #[serde(
+        rename = "nbf",
+        default,
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub invalid_before: Option<UnixTimeStamp>,
+
+    /// Issuer
+    #[serde(
+        rename = "iss",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_str"
+    )]
+    pub issuer: Option<Cow<'a, str>>,
+
+    /// Subject
+    #[serde(
+        rename = "sub",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_str"
+    )]
+    pub subject: Option<Cow<'a, str>>,
+
+    /// Audience
+    #[serde(
+        rename = "aud",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_audiences"
+    )]
+    pub audiences: Option<BorrowedAudiences<'a>>,
+
+    /// JWT identifier
+    #[serde(
+        rename = "jti",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_str"
+    )]
+    pub jwt_id: Option<Cow<'a, str>>,
+
+    /// Nonce
+    #[serde(
+        rename = "nonce",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_str"
+    )]
+    pub nonce: Option<Cow<'a, str>>,
+
+    /// OpenID Connect authentication time
+    #[serde(
+        rename = "auth_time",
+        default,
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub auth_time: Option<UnixTimeStamp>,
+
+    /// OpenID Connect authentication methods
+    #[serde(
+        rename = "amr",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_str_seq"
+    )]
+    pub authentication_methods: Option<Vec<Cow<'a, str>>>,
+
+    /// OpenID Connect authentication context class
+    #[serde(
+        rename = "acr",
+        default,
+        borrow,
+        deserialize_with = "self::serde_additions::borrowed::deserialize_str"
+    )]
+    pub auth_context_class: Option<Cow<'a, str>>,
+}
+
+/// Audiences borrowed from the decoded payload of a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BorrowedAudiences<'a> {
+    /// Audiences represented as a set, in their original order, without duplicates
+    AsSet(Vec<Cow<'a, str>>),
+    /// An audience represented as a string
+    AsString(Cow<'a, str>),
+}
+
+impl BorrowedAudiences<'_> {
+    /// Return the first audience, if there is one
+    ///
+    /// An empty string counts as no audiences.
+    pub fn first(&self) -> Option<&str> {
+        match self {
+            BorrowedAudiences::AsSet(audiences) => audiences.first().map(|x| x.as_ref()),
+            BorrowedAudiences::AsString(audience) if audience.is_empty() => None,
+            BorrowedAudiences::AsString(audience) => Some(audience),
         }
-        if let Some(time_issued) = self.issued_at {
-            ensure!(time_issued <= now + time_tolerance, JWTError::ClockDrift);
-            if let Some(max_validity) = options.max_validity {
-                ensure!(
-                    now <= time_issued || now - time_issued <= max_validity,
-                    JWTError::TokenIsTooOld
-                );
-            }
+    }
+
+    /// Return `true` if `audience` is one of the audiences
+    pub fn contains(&self, audience: &str) -> bool {
+        match self {
+            BorrowedAudiences::AsSet(audiences) => audiences.iter().any(|x| x == audience),
+            BorrowedAudiences::AsString(x) => x == audience,
         }
-        if !options.accept_future {
-            if let Some(invalid_before) = self.invalid_before {
-                ensure!(now >= invalid_before, JWTError::TokenNotValidYet);
+    }
+}
+
+impl BorrowedClaims<'_> {
+    /// Check the standard claims against the verification options
+    ///
+    /// This is done automatically during token verification.
+    pub fn validate(&self, options: &VerificationOptions) -> Result<(), Error> {
+        let contains_audience = |audience: &str| match &self.audiences {
+            Some(audiences) => audiences.contains(audience),
+            None => false,
+        };
+        let all_audiences = |predicate: AudiencePredicate| match &self.audiences {
+            Some(BorrowedAudiences::AsSet(audiences)) => audiences.iter().all(|x| predicate(x)),
+            _ => true,
+        };
+        let audiences = match &self.audiences {
+            None => None,
+            Some(BorrowedAudiences::AsString(audience)) => {
+                Some(AudiencesView::AsString(audience.as_ref()))
             }
+            Some(BorrowedAudiences::AsSet(_)) => Some(AudiencesView::AsSet {
+                contains: &contains_audience,
+                all: &all_audiences,
+            }),
+        };
+        let contains_authentication_method = |method: &str| {
+            self.authentication_methods
+                .as_ref()
+                .is_some_and(|methods| methods.iter().any(|x| x == method))
+        };
+        let view = ClaimsView {
+            issued_at: self.issued_at,
+            expires_at: self.expires_at,
+            invalid_before: self.invalid_before,
+            auth_time: self.auth_time,
+            issuer: self.issuer.as_deref(),
+            subject: self.subject.as_deref(),
+            jwt_id: self.jwt_id.as_deref(),
+            nonce: self.nonce.as_deref(),
+            audiences,
+            authentication_methods: self
+                .authentication_methods
+                .as_ref()
+                .map(|_| &contains_authentication_method as &dyn Fn(&str) -> bool),
+            auth_context_class: self.auth_context_class.as_deref(),
+        };
+        match view.validation_errors(options, true).into_iter().next() {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
         }
-        if let Some(expires_at) = self.expires_at {
-            ensure!(
-                now - time_tolerance <= expires_at,
-                JWTError::TokenHasExpired
-            );
+    }
+}
+
+impl<CustomClaims> JWTClaims<CustomClaims> {
+    /// Check the standard claims against the verification options
+    ///
+    /// This is done automatically during token verification. It can also be used on claims
+    /// obtained through an already authenticated channel, as it doesn't involve any key:
+    /// only time-related claims, the issuer, subject, nonce, audiences and authentication
+    /// methods and context are checked.
+    ///
+    /// Absent time claims are not checked, unless `VerificationOptions::require_expiry` is set.
+    pub fn validate(&self, options: &VerificationOptions) -> Result<(), Error> {
+        match self.validation_errors(options, true).into_iter().next() {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
         }
-        if let Some(required_issuer) = &options.required_issuer {
-            if let Some(issuer) = &self.issuer {
-                ensure!(issuer == required_issuer, JWTError::RequiredIssuerMismatch);
-            } else {
-                bail!(JWTError::RequiredIssuerMissing);
+    }
+
+    /// Serialize the claims to JSON, exactly as they are serialized in tokens
+    ///
+    /// For claims returned by a verification function, this matches the payload of the token,
+    /// as long as the custom claims type preserves the order of its members.
+    pub fn to_json(&self) -> Result<String, Error>
+    where
+        CustomClaims: Serialize,
+    {
+        if self.rfc3339_timestamps {
+            #[cfg(not(feature = "chrono"))]
+            bail!(JWTError::RFC3339TimestampsUnsupported);
+            #[cfg(feature = "chrono")]
+            {
+                let mut claims_value = serde_json::to_value(self)?;
+                serde_additions::rfc3339::encode(&mut claims_value);
+                return Ok(serde_json::to_string(&claims_value)?);
             }
         }
-        if let Some(required_subject) = &options.required_subject {
-            if let Some(subject) = &self.subject {
-                ensure!(
-                    subject == required_subject,
-                    JWTError::RequiredSubjectMismatch
-                );
-            } else {
-                bail!(JWTError::RequiredSubjectMissing);
-            }
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Return the names of the claims that are present, including custom claims
+    ///
+    /// Names are returned in lexicographic order. Custom claims serialized as `null` are included.
+    pub fn present_claim_names(&self) -> Vec<String>
+    where
+        CustomClaims: Serialize,
+    {
+        let mut names: Vec<String> = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(claims)) => claims.keys().cloned().collect(),
+            _ => vec![],
+        };
+        names.sort();
+        names
+    }
+
+    /// Return `true` if the expiration date of the token has passed
+    ///
+    /// The time tolerance is not taken into account. This is mainly useful to know if a token
+    /// has been accepted only because of the `accept_expired` verification option.
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Clock::now_since_epoch() > expires_at,
+            None => false,
         }
-        if let Some(required_nonce) = &options.required_nonce {
-            if let Some(nonce) = &self.nonce {
-                ensure!(nonce == required_nonce, JWTError::RequiredNonceMismatch);
-            } else {
-                bail!(JWTError::RequiredNonceMissing);
-            }
+    }
+
+    /// Check the standard claims against the verification options, and return all the
+    /// reasons why they would be rejected, instead of stopping at the first one.
+    ///
+    /// This is a diagnostics tool. Token verification functions only report the first error.
+    pub fn validate_collect(&self, options: &VerificationOptions) -> Result<(), Vec<JWTError>> {
+        let errors = self.validation_errors(options, false);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
-        if let Some(required_audience) = &options.required_audience {
-            if let Some(audiences) = &self.audiences {
-                match audiences {
-                    Audiences::AsString(audience) => ensure!(
-                        audience == required_audience,
-                        JWTError::RequiredAudienceMismatch
-                    ),
-                    Audiences::AsSet(audiences) => ensure!(
-                        audiences.contains(required_audience),
-                        JWTError::RequiredAudienceMismatch
-                    ),
-                };
-            } else {
-                bail!(JWTError::RequiredAudienceMissing);
-            }
+    }
+
+    fn validation_errors(&self, options: &VerificationOptions, fail_fast: bool) -> Vec<JWTError> {
+        let contains_audience = |audience: &str| match &self.audiences {
+            Some(Audiences::AsSet(audiences)) => audiences.contains(audience),
+            _ => false,
+        };
+        let all_audiences = |predicate: AudiencePredicate| match &self.audiences {
+            Some(Audiences::AsSet(audiences)) => audiences.iter().all(|x| predicate(x)),
+            _ => true,
+        };
+        let audiences = match &self.audiences {
+            None => None,
+            Some(Audiences::AsString(audience)) => Some(AudiencesView::AsString(audience)),
+            Some(Audiences::AsSet(_)) => Some(AudiencesView::AsSet {
+                contains: &contains_audience,
+                all: &all_audiences,
+            }),
+        };
+        let contains_authentication_method = |method: &str| {
+            self.authentication_methods
+                .as_ref()
+                .is_some_and(|methods| methods.iter().any(|x| x == method))
+        };
+        ClaimsView {
+            issued_at: self.issued_at,
+            expires_at: self.expires_at,
+            invalid_before: self.invalid_before,
+            auth_time: self.auth_time,
+            issuer: self.issuer.as_deref(),
+            subject: self.subject.as_deref(),
+            jwt_id: self.jwt_id.as_deref(),
+            nonce: self.nonce.as_deref(),
+            audiences,
+            authentication_methods: self
+                .authentication_methods
+                .as_ref()
+                .map(|_| &contains_authentication_method as &dyn Fn(&str) -> bool),
+            auth_context_class: self.auth_context_class.as_deref(),
         }
-        Ok(())
+        .validation_errors(options, fail_fast)
+    }
+
+    /// Truncate timestamps the same way they are truncated during serialization
+    pub(crate) fn truncate_timestamps(mut self) -> Self {
+        self.issued_at = self.issued_at.map(serde_additions::floor_to_secs);
+        self.expires_at = self.expires_at.map(serde_additions::floor_to_secs);
+        self.invalid_before = self.invalid_before.map(serde_additions::floor_to_secs);
+        self.auth_time = self.auth_time.map(serde_additions::floor_to_secs);
+        self
+    }
+
+    /// Set the token as not being valid until `unix_timestamp`
+    ///
+    /// `unix_timestamp` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    #[must_use]
+    pub fn with_invalid_before(mut self, unix_timestamp: impl ToUnixTimeStamp) -> Self {
+        self.invalid_before = Some(unix_timestamp.to_unix_timestamp());
+        self
     }
 
     /// Set the token as not being valid until `unix_timestamp`
-    pub fn invalid_before(mut self, unix_timestamp: UnixTimeStamp) -> Self {
-        self.invalid_before = Some(unix_timestamp);
+    #[deprecated(note = "use `with_invalid_before()` instead")]
+    #[must_use]
+    pub fn invalid_before(self, unix_timestamp: impl ToUnixTimeStamp) -> Self {
+        self.with_invalid_before(unix_timestamp)
+    }
+
+    /// Remove the date the token is not valid before
+    ///
+    /// `Claims::create()` sets it to the creation date, which some verifiers consider redundant.
+    #[must_use]
+    pub fn without_not_before(mut self) -> Self {
+        self.invalid_before = None;
+        self
+    }
+
+    /// Set the creation date of the token to `unix_timestamp`
+    ///
+    /// `unix_timestamp` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    #[must_use]
+    pub fn with_issued_at(mut self, unix_timestamp: impl ToUnixTimeStamp) -> Self {
+        self.issued_at = Some(unix_timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// Set the expiration date of the token to `unix_timestamp`
+    ///
+    /// `unix_timestamp` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    #[must_use]
+    pub fn with_expires_at(mut self, unix_timestamp: impl ToUnixTimeStamp) -> Self {
+        self.expires_at = Some(unix_timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// Make the token valid from now, and for `valid_for`
+    ///
+    /// The creation date is set to the current time, and the expiration date to `valid_for` after it.
+    /// If a "not before" date was present, it is also set to the current time.
+    /// This is useful to re-issue claims received from another party, signed with a different key.
+    #[must_use]
+    pub fn refreshed(mut self, valid_for: Duration) -> Self {
+        self.touch(valid_for);
+        self
+    }
+
+    /// Make the token valid from now, and for `valid_for`, in place
+    ///
+    /// This is the same as `refreshed()`, for claims that are not owned, such as decoded claims
+    /// being re-issued. All other claims are left unchanged.
+    pub fn touch(&mut self, valid_for: Duration) -> &mut Self {
+        let now = serde_additions::floor_to_secs(Clock::now_since_epoch());
+        self.issued_at = Some(now);
+        self.expires_at = Some(Claims::expiration_for(valid_for, now));
+        if self.invalid_before.is_some() {
+            self.invalid_before = Some(now);
+        }
+        self
+    }
+
+    /// Set the creation date of the token to `unix_timestamp`, in place
+    ///
+    /// `unix_timestamp` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    pub fn set_issued_at(&mut self, unix_timestamp: impl ToUnixTimeStamp) -> &mut Self {
+        self.issued_at = Some(unix_timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// Set the expiration date of the token to `unix_timestamp`, in place
+    ///
+    /// `unix_timestamp` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    pub fn set_expires_at(&mut self, unix_timestamp: impl ToUnixTimeStamp) -> &mut Self {
+        self.expires_at = Some(unix_timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// Set the JWT identifier, in place
+    pub fn set_jwt_id(&mut self, jwt_id: impl ToString) -> &mut Self {
+        self.jwt_id = Some(jwt_id.to_string());
+        self
+    }
+
+    /// Remove the expiration date of the token
+    #[must_use]
+    pub fn without_expiry(mut self) -> Self {
+        self.expires_at = None;
+        self
+    }
+
+    /// Serialize time claims as RFC 3339 strings instead of numbers
+    ///
+    /// This is not compliant with the JWT specification, and should only be used for tokens
+    /// exchanged between cooperating services.
+    #[cfg(feature = "chrono")]
+    #[must_use]
+    pub fn with_rfc3339_timestamps(mut self) -> Self {
+        self.rfc3339_timestamps = true;
         self
     }
 
     /// Set the issuer
+    #[must_use]
     pub fn with_issuer(mut self, issuer: impl ToString) -> Self {
         self.issuer = Some(issuer.to_string());
         self
     }
 
     /// Set the subject
+    #[must_use]
     pub fn with_subject(mut self, subject: impl ToString) -> Self {
         self.subject = Some(subject.to_string());
         self
     }
 
     /// Register one or more audiences (optional recipient identifiers), as an set
+    #[must_use]
     pub fn with_audiences(mut self, audiences: HashSet<impl ToString>) -> Self {
         self.audiences = Some(Audiences::AsSet(
             audiences.iter().map(|x| x.to_string()).collect(),
@@ -254,72 +803,757 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
         self
     }
 
+    /// Register one or more audiences (optional recipient identifiers), as a set built from any iterator
+    ///
+    /// Duplicate audiences are removed.
+    #[must_use]
+    pub fn with_audiences_iter(
+        mut self,
+        audiences: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.audiences = Some(Audiences::AsSet(
+            audiences.into_iter().map(|x| x.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Register one or more audiences (optional recipient identifiers), as a single string
+    ///
+    /// Audiences are joined with `delimiter`. This is not standard, but some verifiers expect
+    /// audiences to be represented this way. Duplicate audiences are removed.
+    #[must_use]
+    pub fn with_audiences_as_string(
+        mut self,
+        audiences: impl IntoIterator<Item = impl ToString>,
+        delimiter: &str,
+    ) -> Self {
+        let audiences: Audiences = audiences.into_iter().map(|x| x.to_string()).collect();
+        self.audiences = Some(Audiences::AsString(audiences.into_joined_string(delimiter)));
+        self
+    }
+
     /// Set a unique audience (an optional recipient identifier), as a string
+    #[must_use]
     pub fn with_audience(mut self, audience: impl ToString) -> Self {
         self.audiences = Some(Audiences::AsString(audience.to_string()));
         self
     }
 
+    /// Represent the audiences either as a string or as a set
+    ///
+    /// This is useful to forward decoded claims to a verifier expecting a specific representation.
+    /// A set containing more than one audience cannot be converted to a string, and is left unchanged;
+    /// `JWTError::TooManyAudiences` is returned in that case. Absent audiences are left absent.
+    pub fn set_audience_format(&mut self, as_string: bool) -> Result<&mut Self, Error> {
+        let audiences = match self.audiences.take() {
+            None => return Ok(self),
+            Some(audiences) => audiences,
+        };
+        self.audiences = Some(match audiences {
+            Audiences::AsSet(audiences_set) if as_string && audiences_set.len() > 1 => {
+                self.audiences = Some(Audiences::AsSet(audiences_set));
+                bail!(JWTError::TooManyAudiences);
+            }
+            audiences if as_string => Audiences::AsString(audiences.into_string()?),
+            audiences => Audiences::AsSet(audiences.into_set()),
+        });
+        Ok(self)
+    }
+
     /// Set the JWT identifier
+    #[must_use]
     pub fn with_jwt_id(mut self, jwt_id: impl ToString) -> Self {
         self.jwt_id = Some(jwt_id.to_string());
         self
     }
 
     /// Set the nonce
+    #[must_use]
     pub fn with_nonce(mut self, nonce: impl ToString) -> Self {
         self.nonce = Some(nonce.to_string());
         self
     }
 
-    /// Create a nonce, attach it and return it
-    pub fn create_nonce(&mut self) -> String {
-        let mut raw_nonce = [0u8; 24];
-        let mut rng = rand::thread_rng();
-        rng.fill_bytes(&mut raw_nonce);
-        let nonce = Base64UrlSafeNoPadding::encode_to_string(raw_nonce).unwrap();
-        self.nonce = Some(nonce);
-        self.nonce.as_deref().unwrap().to_string()
+    /// Set the OpenID Connect authentication methods, such as `pwd` or `mfa`
+    #[must_use]
+    pub fn with_authentication_methods(
+        mut self,
+        methods: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.authentication_methods = Some(methods.into_iter().map(|x| x.to_string()).collect());
+        self
     }
-}
 
-pub struct Claims;
+    /// Set the OpenID Connect authentication context class
+    #[must_use]
+    pub fn with_auth_context_class(mut self, auth_context_class: impl ToString) -> Self {
+        self.auth_context_class = Some(auth_context_class.to_string());
+        self
+    }
 
-impl Claims {
-    /// Create a new set of claims, without custom data, expiring in `valid_for`.
-    pub fn create(valid_for: Duration) -> JWTClaims<NoCustomClaims> {
-        let now = Some(Clock::now_since_epoch());
-        JWTClaims {
-            issued_at: now,
-            expires_at: Some(now.unwrap() + valid_for),
-            invalid_before: now,
-            audiences: None,
-            issuer: None,
-            jwt_id: None,
-            subject: None,
-            nonce: None,
-            custom: NoCustomClaims {},
+    /// Set the OpenID Connect authentication time
+    #[must_use]
+    pub fn with_auth_time(mut self, unix_timestamp: impl ToUnixTimeStamp) -> Self {
+        self.auth_time = Some(unix_timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// Set the OpenID Connect access token hash
+    ///
+    /// `alg` is the JWT algorithm used by the ID token, that determines the hash function.
+    pub fn with_access_token_hash(mut self, access_token: &str, alg: &str) -> Result<Self, Error> {
+        self.access_token_hash = Some(oidc_hash(access_token, alg)?);
+        Ok(self)
+    }
+
+    /// Set the OpenID Connect authorization code hash
+    ///
+    /// `alg` is the JWT algorithm used by the ID token, that determines the hash function.
+    pub fn with_code_hash(mut self, code: &str, alg: &str) -> Result<Self, Error> {
+        self.code_hash = Some(oidc_hash(code, alg)?);
+        Ok(self)
+    }
+
+    /// Check that the access token hash matches a given access token
+    ///
+    /// `alg` is the JWT algorithm used by the ID token, as returned by `TokenMetadata::algorithm()`.
+    pub fn verify_access_token_hash(&self, access_token: &str, alg: &str) -> Result<(), Error> {
+        let access_token_hash = self
+            .access_token_hash
+            .as_ref()
+            .ok_or(JWTError::AccessTokenHashMissing)?;
+        ensure!(
+            access_token_hash == &oidc_hash(access_token, alg)?,
+            JWTError::AccessTokenHashMismatch
+        );
+        Ok(())
+    }
+
+    /// Check that the authorization code hash matches a given code
+    ///
+    /// `alg` is the JWT algorithm used by the ID token, as returned by `TokenMetadata::algorithm()`.
+    pub fn verify_code_hash(&self, code: &str, alg: &str) -> Result<(), Error> {
+        let code_hash = self.code_hash.as_ref().ok_or(JWTError::CodeHashMissing)?;
+        ensure!(
+            code_hash == &oidc_hash(code, alg)?,
+            JWTError::CodeHashMismatch
+        );
+        Ok(())
+    }
+
+    /// Set the DPoP HTTP method and URI of the request the proof is attached to
+    ///
+    /// The query and fragment of `uri` are removed.
+    #[must_use]
+    pub fn with_http_request(mut self, method: impl ToString, uri: &str) -> Self {
+        self.http_method = Some(method.to_string());
+        self.http_uri = Some(uri_without_query(uri).to_string());
+        self
+    }
+
+    /// Set the DPoP access token hash, binding the proof to `access_token`
+    #[must_use]
+    pub fn with_dpop_access_token_hash(mut self, access_token: &str) -> Self {
+        self.dpop_access_token_hash = Some(dpop_hash(access_token));
+        self
+    }
+
+    /// Check that the DPoP HTTP method and URI match the actual request
+    ///
+    /// The method is compared case-sensitively. The query and fragment of the URIs are ignored,
+    /// but no other normalization is done.
+    pub fn verify_http_request(&self, method: &str, uri: &str) -> Result<(), Error> {
+        let http_method = self
+            .http_method
+            .as_ref()
+            .ok_or(JWTError::HttpMethodMissing)?;
+        ensure!(http_method == method, JWTError::HttpMethodMismatch);
+        let http_uri = self.http_uri.as_ref().ok_or(JWTError::HttpUriMissing)?;
+        ensure!(
+            uri_without_query(http_uri) == uri_without_query(uri),
+            JWTError::HttpUriMismatch
+        );
+        Ok(())
+    }
+
+    /// Check that the DPoP access token hash matches the access token the proof is bound to
+    pub fn verify_dpop_access_token_hash(&self, access_token: &str) -> Result<(), Error> {
+        let dpop_access_token_hash = self
+            .dpop_access_token_hash
+            .as_ref()
+            .ok_or(JWTError::DPoPAccessTokenHashMissing)?;
+        ensure!(
+            dpop_access_token_hash == &dpop_hash(access_token),
+            JWTError::DPoPAccessTokenHashMismatch
+        );
+        Ok(())
+    }
+
+    /// Create a nonce, attach it and return it
+    pub fn create_nonce(&mut self) -> String {
+        self.create_nonce_with_rng(rand::thread_rng())
+    }
+
+    /// Create a nonce using a custom random number generator, attach it and return it
+    pub fn create_nonce_with_rng(&mut self, mut rng: impl RngCore + CryptoRng) -> String {
+        let mut raw_nonce = [0u8; 24];
+        rng.fill_bytes(&mut raw_nonce);
+        let nonce = Base64UrlSafeNoPadding::encode_to_string(raw_nonce).unwrap();
+        self.nonce = Some(nonce);
+        self.nonce.as_deref().unwrap().to_string()
+    }
+}
+
+/// The standard claims, as needed for validation, borrowed from either owned or borrowed claims
+struct ClaimsView<'t> {
+    issued_at: Option<UnixTimeStamp>,
+    expires_at: Option<UnixTimeStamp>,
+    invalid_before: Option<UnixTimeStamp>,
+    auth_time: Option<UnixTimeStamp>,
+    issuer: Option<&'t str>,
+    subject: Option<&'t str>,
+    jwt_id: Option<&'t str>,
+    nonce: Option<&'t str>,
+    audiences: Option<AudiencesView<'t>>,
+    authentication_methods: Option<&'t dyn Fn(&str) -> bool>,
+    auth_context_class: Option<&'t str>,
+}
+
+/// A predicate on a single audience
+type AudiencePredicate<'p> = &'p dyn Fn(&str) -> bool;
+
+#[derive(Clone, Copy)]
+enum AudiencesView<'t> {
+    AsString(&'t str),
+    AsSet {
+        contains: AudiencePredicate<'t>,
+        all: &'t dyn Fn(AudiencePredicate) -> bool,
+    },
+}
+
+impl AudiencesView<'_> {
+    fn contains(self, delimiters: &[char], audience: &str) -> bool {
+        match self {
+            AudiencesView::AsString(audiences) if !delimiters.is_empty() => {
+                audiences.split(delimiters).any(|x| x == audience)
+            }
+            AudiencesView::AsString(audiences) => audiences == audience,
+            AudiencesView::AsSet { contains, .. } => contains(audience),
+        }
+    }
+
+    fn all(self, delimiters: &[char], predicate: AudiencePredicate) -> bool {
+        match self {
+            AudiencesView::AsString(audiences) if !delimiters.is_empty() => audiences
+                .split(delimiters)
+                .filter(|x| !x.is_empty())
+                .all(predicate),
+            AudiencesView::AsString(audiences) => audiences.is_empty() || predicate(audiences),
+            AudiencesView::AsSet { all, .. } => all(predicate),
         }
     }
+}
+
+impl ClaimsView<'_> {
+    fn validation_errors(&self, options: &VerificationOptions, fail_fast: bool) -> Vec<JWTError> {
+        let mut errors = vec![];
+        macro_rules! check {
+            ($cond:expr, $error:expr) => {
+                if !$cond {
+                    errors.push($error);
+                    if fail_fast {
+                        return errors;
+                    }
+                }
+            };
+        }
+
+        let now = Clock::now_since_epoch();
+        let time_tolerance = options.effective_time_tolerance();
+
+        if let Some(reject_before) = options.reject_before {
+            check!(now <= reject_before, JWTError::OldTokenReused);
+        }
+        if let Some(time_issued) = self.issued_at {
+            check!(
+                time_issued <= now.saturating_add(time_tolerance),
+                JWTError::ClockDrift
+            );
+        }
+        if let Some(max_validity) = options.max_validity {
+            let anchor = match options.max_validity_anchor {
+                TokenAgeAnchor::IssuedAt => self.issued_at,
+                TokenAgeAnchor::IssuedAtOrInvalidBefore => self.issued_at.or(self.invalid_before),
+                TokenAgeAnchor::InvalidBefore => self.invalid_before,
+            };
+            if let Some(anchor) = anchor {
+                check!(
+                    now <= anchor || now - anchor <= max_validity,
+                    JWTError::TokenIsTooOld
+                );
+            }
+        }
+        if !options.accept_future {
+            if let Some(invalid_before) = self.invalid_before {
+                check!(now >= invalid_before, JWTError::TokenNotValidYet);
+            }
+        }
+        if let Some(expires_at) = self.expires_at {
+            let grace = match (options.accept_expired, options.expired_grace) {
+                (false, _) => Some(Duration::from_secs(0)),
+                (true, expired_grace) => expired_grace,
+            };
+            if let Some(grace) = grace {
+                check!(
+                    now.saturating_sub(time_tolerance) <= expires_at.saturating_add(grace),
+                    JWTError::TokenHasExpired
+                );
+            }
+        } else if options.require_expiry {
+            check!(false, JWTError::MissingExpiry);
+        }
+        if let (Some(time_issued), Some(expires_at)) = (self.issued_at, self.expires_at) {
+            check!(
+                options.allow_inconsistent_time_claims || time_issued <= expires_at,
+                JWTError::InconsistentTimeClaims
+            );
+        }
+        if let Some(required_issuer) = &options.required_issuer {
+            if let Some(issuer) = self.issuer {
+                check!(
+                    if options.canonicalize_issuer {
+                        canonical_issuer(issuer) == canonical_issuer(required_issuer)
+                    } else {
+                        issuer == required_issuer
+                    },
+                    JWTError::RequiredIssuerMismatch
+                );
+            } else {
+                check!(false, JWTError::RequiredIssuerMissing);
+            }
+        }
+        if let Some(required_subject) = &options.required_subject {
+            if let Some(subject) = self.subject {
+                check!(
+                    subject == required_subject,
+                    JWTError::RequiredSubjectMismatch
+                );
+            } else {
+                check!(false, JWTError::RequiredSubjectMissing);
+            }
+        }
+        if let Some(allowed_subjects) = &options.allowed_subjects {
+            if let Some(subject) = self.subject {
+                check!(
+                    allowed_subjects.contains(subject),
+                    JWTError::RequiredSubjectMismatch
+                );
+            } else {
+                check!(false, JWTError::RequiredSubjectMissing);
+            }
+        }
+        if let Some(required_nonce) = &options.required_nonce {
+            if let Some(nonce) = self.nonce {
+                check!(nonce == required_nonce, JWTError::RequiredNonceMismatch);
+            } else {
+                check!(false, JWTError::RequiredNonceMissing);
+            }
+        }
+        if let Some(required_amr) = &options.required_amr {
+            if let Some(contains_authentication_method) = self.authentication_methods {
+                check!(
+                    required_amr
+                        .iter()
+                        .all(|method| contains_authentication_method(method)),
+                    JWTError::RequiredAmrMismatch
+                );
+            } else {
+                check!(false, JWTError::RequiredAmrMissing);
+            }
+        }
+        if let Some(required_acr) = &options.required_acr {
+            if let Some(auth_context_class) = self.auth_context_class {
+                check!(
+                    auth_context_class == required_acr,
+                    JWTError::RequiredAcrMismatch
+                );
+            } else {
+                check!(false, JWTError::RequiredAcrMissing);
+            }
+        }
+        if let Some(max_auth_age) = options.max_auth_age {
+            if let Some(auth_time) = self.auth_time {
+                check!(
+                    now <= auth_time || now - auth_time <= max_auth_age,
+                    JWTError::AuthTooOld
+                );
+            } else {
+                check!(false, JWTError::AuthTimeMissing);
+            }
+        }
+        let required_claims = options.required_claims;
+        if !required_claims.is_empty() {
+            let present = [
+                (RequiredClaims::ISSUED_AT, self.issued_at.is_some()),
+                (RequiredClaims::EXPIRES_AT, self.expires_at.is_some()),
+                (
+                    RequiredClaims::INVALID_BEFORE,
+                    self.invalid_before.is_some(),
+                ),
+                (RequiredClaims::ISSUER, self.issuer.is_some()),
+                (RequiredClaims::SUBJECT, self.subject.is_some()),
+                (RequiredClaims::AUDIENCES, self.audiences.is_some()),
+                (RequiredClaims::JWT_ID, self.jwt_id.is_some()),
+                (RequiredClaims::NONCE, self.nonce.is_some()),
+                (RequiredClaims::AUTH_TIME, self.auth_time.is_some()),
+            ];
+            for (claim, is_present) in present.iter() {
+                if required_claims.contains(*claim) {
+                    check!(*is_present, claim_missing_error(*claim));
+                }
+            }
+        }
+        let delimiters = options.audience_delimiters.as_slice();
+        if options.required_audience.is_some() || options.required_audiences.is_some() {
+            match self.audiences {
+                Some(audiences) => {
+                    let required_audiences = options
+                        .required_audience
+                        .iter()
+                        .chain(options.required_audiences.iter().flatten());
+                    for required_audience in required_audiences {
+                        check!(
+                            audiences.contains(delimiters, required_audience),
+                            JWTError::RequiredAudienceMismatch
+                        );
+                    }
+                }
+                None => check!(false, JWTError::RequiredAudienceMissing),
+            }
+        }
+        if let (Some(allowed_audiences), Some(audiences)) =
+            (&options.allowed_audiences, self.audiences)
+        {
+            check!(
+                audiences.all(delimiters, &|audience| allowed_audiences.contains(audience)),
+                JWTError::AudienceNotAllowed
+            );
+        }
+        errors
+    }
+}
+
+/// Lowercase the scheme and host of an issuer URL, and remove a single trailing slash
+fn canonical_issuer(issuer: &str) -> String {
+    let issuer = issuer.strip_suffix('/').unwrap_or(issuer);
+    match issuer.find("://") {
+        Some(scheme_len) => {
+            let authority_start = scheme_len + 3;
+            let authority_len = issuer[authority_start..]
+                .find(['/', '?', '#'])
+                .unwrap_or(issuer.len() - authority_start);
+            let (scheme_and_host, rest) = issuer.split_at(authority_start + authority_len);
+            scheme_and_host.to_ascii_lowercase() + rest
+        }
+        None => issuer.to_string(),
+    }
+}
+
+/// The error returned when a required standard claim is missing
+fn claim_missing_error(claim: RequiredClaims) -> JWTError {
+    match claim {
+        RequiredClaims::EXPIRES_AT => JWTError::MissingExpiry,
+        RequiredClaims::ISSUER => JWTError::RequiredIssuerMissing,
+        RequiredClaims::SUBJECT => JWTError::RequiredSubjectMissing,
+        RequiredClaims::AUDIENCES => JWTError::RequiredAudienceMissing,
+        RequiredClaims::NONCE => JWTError::RequiredNonceMissing,
+        RequiredClaims::AUTH_TIME => JWTError::AuthTimeMissing,
+        _ => JWTError::RequiredClaimMissing(claim.names().first().copied().unwrap_or_default()),
+    }
+}
+
+/// Compute an OpenID Connect `at_hash` or `c_hash` value: the base64url encoding of the
+/// left-most half of the hash of `value`, using the hash function of the `alg` JWT algorithm.
+fn oidc_hash(value: &str, alg: &str) -> Result<String, Error> {
+    let hash = match alg {
+        "HS256" | "RS256" | "PS256" | "ES256" | "ES256K" => {
+            hmac_sha256::Hash::hash(value.as_bytes()).to_vec()
+        }
+        "HS384" | "RS384" | "PS384" => hmac_sha512::sha384::Hash::hash(value.as_bytes()).to_vec(),
+        "HS512" | "RS512" | "PS512" | "EdDSA" => hmac_sha512::Hash::hash(value.as_bytes()).to_vec(),
+        _ => bail!(JWTError::UnsupportedAlgorithm),
+    };
+    Ok(Base64UrlSafeNoPadding::encode_to_string(
+        &hash[..hash.len() / 2],
+    )?)
+}
+
+/// Compute a DPoP `ath` hash, which always uses SHA-256, unlike OpenID Connect hashes
+fn dpop_hash(access_token: &str) -> String {
+    Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(access_token.as_bytes()))
+        .unwrap()
+}
+
+fn uri_without_query(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or_default()
+}
+
+pub struct Claims;
+
+impl Claims {
+    /// Create a new set of claims, without custom data, expiring in `valid_for`.
+    ///
+    /// All timestamps are truncated to whole seconds, toward the past.
+    pub fn create(valid_for: Duration) -> JWTClaims<NoCustomClaims> {
+        Self::create_with_time(Clock::now_since_epoch(), valid_for)
+    }
+
+    /// Create a new set of claims, without custom data, created at `now` and expiring in `valid_for`.
+    ///
+    /// `now` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    /// All timestamps are truncated to whole seconds, toward the past.
+    pub fn create_with_time(
+        now: impl ToUnixTimeStamp,
+        valid_for: Duration,
+    ) -> JWTClaims<NoCustomClaims> {
+        Self::with_custom_claims_at(NoCustomClaims {}, now, valid_for)
+    }
 
     /// Create a new set of claims, with custom data, expiring in `valid_for`.
+    ///
+    /// All timestamps are truncated to whole seconds, toward the past.
     pub fn with_custom_claims<CustomClaims: Serialize + DeserializeOwned>(
         custom_claims: CustomClaims,
         valid_for: Duration,
     ) -> JWTClaims<CustomClaims> {
-        let now = Some(Clock::now_since_epoch());
+        Self::with_custom_claims_at(custom_claims, Clock::now_since_epoch(), valid_for)
+    }
+
+    /// Create a new set of claims, with custom data, created at `now` and expiring in `valid_for`.
+    ///
+    /// `now` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    /// All timestamps are truncated to whole seconds, toward the past.
+    pub fn with_custom_claims_at<CustomClaims: Serialize + DeserializeOwned>(
+        custom_claims: CustomClaims,
+        now: impl ToUnixTimeStamp,
+        valid_for: Duration,
+    ) -> JWTClaims<CustomClaims> {
+        let now = serde_additions::floor_to_secs(now.to_unix_timestamp());
         JWTClaims {
-            issued_at: now,
-            expires_at: Some(now.unwrap() + valid_for),
-            invalid_before: now,
+            issued_at: Some(now),
+            expires_at: Some(Self::expiration_for(valid_for, now)),
+            invalid_before: Some(now),
             audiences: None,
             issuer: None,
             jwt_id: None,
             subject: None,
             nonce: None,
+            auth_time: None,
+            authentication_methods: None,
+            auth_context_class: None,
+            access_token_hash: None,
+            code_hash: None,
+            http_method: None,
+            http_uri: None,
+            dpop_access_token_hash: None,
+            rfc3339_timestamps: false,
             custom: custom_claims,
         }
     }
+
+    /// Return the expiration date that claims created at `now` and valid for `valid_for` would get
+    ///
+    /// This matches the `expires_at` value set by `create_with_time()`, without having to create the claims.
+    pub fn expiration_for(valid_for: Duration, now: impl ToUnixTimeStamp) -> UnixTimeStamp {
+        let now = serde_additions::floor_to_secs(now.to_unix_timestamp());
+        serde_additions::floor_to_secs(now.saturating_add(valid_for))
+    }
+
+    /// Start building a set of claims, whose expiration date must be set before they can be used
+    ///
+    /// See `ClaimsBuilder`.
+    pub fn builder() -> ClaimsBuilder<NoExp> {
+        ClaimsBuilder::new()
+    }
+}
+
+/// Marker for a `ClaimsBuilder` without an expiration date
+pub struct NoExp;
+
+/// Marker for a `ClaimsBuilder` with an expiration date
+pub struct HasExp;
+
+/// A builder for claims, that can only build them once an expiration date has been set
+///
+/// This makes it impossible to mint tokens without an expiration date by mistake:
+///
+/// ```
+/// use jwt_simple::prelude::*;
+///
+/// let claims = Claims::builder()
+///     .with_subject("subject")
+///     .expires_in(Duration::from_hours(2))
+///     .build();
+/// assert!(claims.expires_at.is_some());
+/// ```
+///
+/// ```compile_fail
+/// use jwt_simple::prelude::*;
+///
+/// let claims = Claims::builder().with_subject("subject").build();
+/// ```
+pub struct ClaimsBuilder<Exp, CustomClaims = NoCustomClaims> {
+    claims: JWTClaims<CustomClaims>,
+    exp: PhantomData<Exp>,
+}
+
+impl ClaimsBuilder<NoExp> {
+    /// Create a builder for claims without custom data, created now
+    pub fn new() -> Self {
+        let mut claims = Claims::create(Duration::from_secs(0));
+        claims.expires_at = None;
+        ClaimsBuilder {
+            claims,
+            exp: PhantomData,
+        }
+    }
+}
+
+impl Default for ClaimsBuilder<NoExp> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<CustomClaims> ClaimsBuilder<NoExp, CustomClaims> {
+    /// Make the claims expire `valid_for` after their creation date
+    #[must_use]
+    pub fn expires_in(mut self, valid_for: Duration) -> ClaimsBuilder<HasExp, CustomClaims> {
+        let issued_at = self.claims.issued_at.unwrap_or_else(Clock::now_since_epoch);
+        self.claims.expires_at = Some(serde_additions::floor_to_secs(
+            issued_at.saturating_add(valid_for),
+        ));
+        ClaimsBuilder {
+            claims: self.claims,
+            exp: PhantomData,
+        }
+    }
+
+    /// Make the claims expire at `unix_timestamp`
+    ///
+    /// `unix_timestamp` can also be a `SystemTime`, or a `chrono::DateTime<Utc>` if the `chrono` feature is enabled.
+    #[must_use]
+    pub fn expires_at(
+        mut self,
+        unix_timestamp: impl ToUnixTimeStamp,
+    ) -> ClaimsBuilder<HasExp, CustomClaims> {
+        self.claims = self.claims.with_expires_at(unix_timestamp);
+        ClaimsBuilder {
+            claims: self.claims,
+            exp: PhantomData,
+        }
+    }
+}
+
+impl<Exp, CustomClaims> ClaimsBuilder<Exp, CustomClaims> {
+    /// Attach custom data, replacing any previous custom data
+    #[must_use]
+    pub fn with_custom_claims<NewCustomClaims>(
+        self,
+        custom_claims: NewCustomClaims,
+    ) -> ClaimsBuilder<Exp, NewCustomClaims> {
+        let claims = self.claims;
+        ClaimsBuilder {
+            claims: JWTClaims {
+                issued_at: claims.issued_at,
+                expires_at: claims.expires_at,
+                invalid_before: claims.invalid_before,
+                issuer: claims.issuer,
+                subject: claims.subject,
+                audiences: claims.audiences,
+                jwt_id: claims.jwt_id,
+                nonce: claims.nonce,
+                auth_time: claims.auth_time,
+                authentication_methods: claims.authentication_methods,
+                auth_context_class: claims.auth_context_class,
+                access_token_hash: claims.access_token_hash,
+                code_hash: claims.code_hash,
+                http_method: claims.http_method,
+                http_uri: claims.http_uri,
+                dpop_access_token_hash: claims.dpop_access_token_hash,
+                rfc3339_timestamps: claims.rfc3339_timestamps,
+                custom: custom_claims,
+            },
+            exp: PhantomData,
+        }
+    }
+
+    /// Set the issuer
+    #[must_use]
+    pub fn with_issuer(mut self, issuer: impl ToString) -> Self {
+        self.claims = self.claims.with_issuer(issuer);
+        self
+    }
+
+    /// Set the subject
+    #[must_use]
+    pub fn with_subject(mut self, subject: impl ToString) -> Self {
+        self.claims = self.claims.with_subject(subject);
+        self
+    }
+
+    /// Set a unique audience, as a string
+    #[must_use]
+    pub fn with_audience(mut self, audience: impl ToString) -> Self {
+        self.claims = self.claims.with_audience(audience);
+        self
+    }
+
+    /// Register one or more audiences, as a set
+    #[must_use]
+    pub fn with_audiences(mut self, audiences: HashSet<impl ToString>) -> Self {
+        self.claims = self.claims.with_audiences(audiences);
+        self
+    }
+
+    /// Register one or more audiences, as a set built from any iterator
+    #[must_use]
+    pub fn with_audiences_iter(
+        mut self,
+        audiences: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.claims = self.claims.with_audiences_iter(audiences);
+        self
+    }
+
+    /// Set the JWT identifier
+    #[must_use]
+    pub fn with_jwt_id(mut self, jwt_id: impl ToString) -> Self {
+        self.claims = self.claims.with_jwt_id(jwt_id);
+        self
+    }
+
+    /// Set the nonce
+    #[must_use]
+    pub fn with_nonce(mut self, nonce: impl ToString) -> Self {
+        self.claims = self.claims.with_nonce(nonce);
+        self
+    }
+
+    /// Set the token as not being valid until `unix_timestamp`
+    #[must_use]
+    pub fn with_invalid_before(mut self, unix_timestamp: impl ToUnixTimeStamp) -> Self {
+        self.claims = self.claims.with_invalid_before(unix_timestamp);
+        self
+    }
+}
+
+impl<CustomClaims> ClaimsBuilder<HasExp, CustomClaims> {
+    /// Return the claims
+    #[must_use]
+    pub fn build(self) -> JWTClaims<CustomClaims> {
+        self.claims
+    }
 }
 
 #[cfg(test)]
@@ -345,4 +1579,672 @@ mod tests {
         assert_eq!(claims.nonce, Some("nonce".to_owned()));
         assert_eq!(claims.subject, Some("subject".to_owned()));
     }
+    #[test]
+    fn should_create_nonce_with_rng() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut claims = Claims::create(Duration::from_mins(10));
+        let nonce = claims.create_nonce_with_rng(StdRng::seed_from_u64(42));
+        assert_eq!(claims.nonce.as_deref(), Some(nonce.as_str()));
+        assert_eq!(
+            nonce,
+            claims.create_nonce_with_rng(StdRng::seed_from_u64(42))
+        );
+        assert_ne!(nonce, claims.create_nonce());
+    }
+
+    #[test]
+    fn should_convert_audiences() {
+        let audiences: Audiences = "a".into();
+        assert!(audiences.is_string());
+        assert_eq!(audiences.len(), 1);
+        assert!(Audiences::from(String::new()).is_empty());
+        let audiences: Audiences = vec!["a".to_string(), "b".to_string(), "a".to_string()]
+            .into_iter()
+            .collect();
+        assert!(audiences.is_set());
+        assert_eq!(audiences.len(), 2);
+        let audiences: Audiences = std::iter::empty().collect();
+        assert!(audiences.is_set());
+        assert!(audiences.is_empty());
+    }
+
+    #[test]
+    fn should_join_string_audiences() {
+        let claims = Claims::create(Duration::from_secs(10))
+            .with_audiences_as_string(vec!["b", "a", "b"], ",");
+        let audiences = claims.audiences.as_ref().unwrap();
+        assert!(audiences.is_string());
+        assert_eq!(audiences, &Audiences::AsString("a,b".to_string()));
+        let options = VerificationOptions {
+            required_audience: Some("b".to_string()),
+            audience_delimiters: vec![','],
+            ..Default::default()
+        };
+        claims.validate(&options).unwrap();
+        let audiences: Audiences = vec!["a".to_string()].into_iter().collect();
+        assert_eq!(audiences.into_joined_string(","), "a");
+    }
+
+    #[test]
+    fn should_list_present_claim_names() {
+        let claims = Claims::create(Duration::from_secs(10)).with_subject("subject");
+        assert_eq!(
+            claims.present_claim_names(),
+            vec!["exp", "iat", "nbf", "sub"]
+        );
+        let mut custom = DynamicClaims::new();
+        custom.insert("user_country".to_string(), "FR".into());
+        let claims = Claims::with_custom_claims(custom, Duration::from_secs(10)).without_expiry();
+        assert_eq!(
+            claims.present_claim_names(),
+            vec!["iat", "nbf", "user_country"]
+        );
+    }
+
+    #[test]
+    fn should_check_allowed_subjects() {
+        let options = VerificationOptions {
+            allowed_subjects: Some(
+                vec!["service a".to_string(), "service b".to_string()]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let claims = Claims::create(Duration::from_secs(10)).with_subject("service b");
+        claims.validate(&options).unwrap();
+        let claims = Claims::create(Duration::from_secs(10)).with_subject("service c");
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredSubjectMismatch)
+        ));
+        let claims = Claims::create(Duration::from_secs(10));
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredSubjectMissing)
+        ));
+    }
+
+    #[test]
+    fn should_check_required_claims() {
+        let options = VerificationOptions {
+            required_claims: RequiredClaims::SUBJECT | RequiredClaims::JWT_ID,
+            ..Default::default()
+        };
+        let claims = Claims::create(Duration::from_secs(10))
+            .with_subject("subject")
+            .with_jwt_id("id");
+        claims.validate(&options).unwrap();
+        let claims = Claims::create(Duration::from_secs(10)).with_jwt_id("id");
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredSubjectMissing)
+        ));
+        let claims = Claims::create(Duration::from_secs(10)).with_subject("subject");
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredClaimMissing("jti"))
+        ));
+    }
+
+    #[test]
+    fn should_treat_null_claims_as_absent() {
+        let claims: JWTClaims<NoCustomClaims> =
+            serde_json::from_str(r#"{"aud":null,"iss":null,"sub":null}"#).unwrap();
+        assert!(claims.audiences.is_none());
+        assert!(claims.issuer.is_none());
+        assert!(claims.subject.is_none());
+        let claims: JWTClaims<NoCustomClaims> = serde_json::from_str(r#"{"aud":"a"}"#).unwrap();
+        assert!(claims.audiences.unwrap().is_string());
+    }
+
+    #[test]
+    fn should_set_audience_format() {
+        let mut claims = Claims::create(Duration::from_secs(10)).with_audience("a");
+        claims.set_audience_format(false).unwrap();
+        assert!(claims.audiences.as_ref().unwrap().is_set());
+        claims.set_audience_format(true).unwrap();
+        assert!(matches!(&claims.audiences, Some(Audiences::AsString(x)) if x == "a"));
+
+        let mut claims =
+            Claims::create(Duration::from_secs(10)).with_audiences_iter(vec!["a", "b"]);
+        assert!(matches!(
+            claims
+                .set_audience_format(true)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::TooManyAudiences)
+        ));
+        assert_eq!(claims.audiences.unwrap().len(), 2);
+
+        let mut claims = Claims::create(Duration::from_secs(10));
+        claims.set_audience_format(true).unwrap();
+        assert!(claims.audiences.is_none());
+    }
+
+    #[test]
+    fn should_verify_dpop_claims() {
+        // Example from RFC 9449, section 7.1
+        let access_token = "Kz~8mXK1EalYznwH-LC-1fBAo.4Ljp~zsPE_NeO.gxU";
+        let claims = Claims::create(Duration::from_mins(1))
+            .with_http_request("GET", "https://resource.example.org/protectedresource?x=1")
+            .with_dpop_access_token_hash(access_token);
+        assert_eq!(
+            claims.http_uri.as_deref(),
+            Some("https://resource.example.org/protectedresource")
+        );
+        assert_eq!(
+            claims.dpop_access_token_hash.as_deref(),
+            Some("fUHyO2r2Z3DZ53EsNrWBb0xWXoaNy59IiKCAqksmQEo")
+        );
+        claims
+            .verify_http_request("GET", "https://resource.example.org/protectedresource#f")
+            .unwrap();
+        claims.verify_dpop_access_token_hash(access_token).unwrap();
+        assert!(matches!(
+            claims
+                .verify_http_request("POST", "https://resource.example.org/protectedresource")
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::HttpMethodMismatch)
+        ));
+        assert!(matches!(
+            claims
+                .verify_http_request("GET", "https://resource.example.org/other")
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::HttpUriMismatch)
+        ));
+        assert!(claims.verify_dpop_access_token_hash("other token").is_err());
+
+        let claims = Claims::create(Duration::from_mins(1));
+        assert!(matches!(
+            claims
+                .verify_http_request("GET", "https://resource.example.org/")
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::HttpMethodMissing)
+        ));
+    }
+
+    #[test]
+    fn should_build_claims_with_expiry() {
+        let claims = ClaimsBuilder::new()
+            .with_subject("subject")
+            .with_audiences_iter(vec!["a", "b"])
+            .with_custom_claims(vec![1, 2, 3])
+            .expires_in(Duration::from_mins(10))
+            .build();
+        assert_eq!(claims.subject.as_deref(), Some("subject"));
+        assert_eq!(claims.custom, vec![1, 2, 3]);
+        assert_eq!(claims.audiences.as_ref().unwrap().len(), 2);
+        assert_eq!(
+            claims.expires_at.unwrap() - claims.issued_at.unwrap(),
+            Duration::from_mins(10)
+        );
+        let claims = Claims::builder()
+            .expires_at(Duration::from_secs(1000))
+            .build();
+        assert_eq!(claims.expires_at, Some(Duration::from_secs(1000)));
+    }
+
+    #[test]
+    fn should_anchor_token_age() {
+        let now = Clock::now_since_epoch();
+        let mut claims =
+            Claims::create_with_time(now - Duration::from_hours(2), Duration::from_hours(10));
+        claims.issued_at = None;
+        let mut options = VerificationOptions {
+            max_validity: Some(Duration::from_hours(1)),
+            ..Default::default()
+        };
+        claims.validate(&options).unwrap();
+        for anchor in [
+            TokenAgeAnchor::IssuedAtOrInvalidBefore,
+            TokenAgeAnchor::InvalidBefore,
+        ]
+        .iter()
+        {
+            options.max_validity_anchor = *anchor;
+            assert!(matches!(
+                claims
+                    .validate(&options)
+                    .err()
+                    .unwrap()
+                    .downcast_ref::<JWTError>(),
+                Some(JWTError::TokenIsTooOld)
+            ));
+        }
+        claims.issued_at = Some(now);
+        options.max_validity_anchor = TokenAgeAnchor::IssuedAtOrInvalidBefore;
+        claims.validate(&options).unwrap();
+    }
+
+    #[test]
+    fn should_canonicalize_issuers() {
+        let claims =
+            Claims::create(Duration::from_secs(10)).with_issuer("HTTPS://Issuer.Example.com/Path/");
+        let mut options = VerificationOptions {
+            required_issuer: Some("https://issuer.example.com/Path".to_string()),
+            ..Default::default()
+        };
+        assert!(claims.validate(&options).is_err());
+        options.canonicalize_issuer = true;
+        claims.validate(&options).unwrap();
+        options.required_issuer = Some("https://issuer.example.com/path".to_string());
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredIssuerMismatch)
+        ));
+        assert_eq!(canonical_issuer("Issuer//"), "Issuer/");
+        assert_eq!(
+            canonical_issuer("https://Example.COM?x=Y"),
+            "https://example.com?x=Y"
+        );
+    }
+
+    #[test]
+    fn should_check_authentication_methods_and_context() {
+        let claims = Claims::create(Duration::from_secs(10))
+            .with_authentication_methods(vec!["pwd", "mfa", "otp"])
+            .with_auth_context_class("urn:example:loa:2");
+        let mut options = VerificationOptions {
+            required_amr: Some(["mfa", "pwd"].iter().map(|x| x.to_string()).collect()),
+            required_acr: Some("urn:example:loa:2".to_string()),
+            ..Default::default()
+        };
+        claims.validate(&options).unwrap();
+        options.required_amr = Some(["mfa", "hwk"].iter().map(|x| x.to_string()).collect());
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredAmrMismatch)
+        ));
+        options.required_amr = None;
+        options.required_acr = Some("urn:example:loa:3".to_string());
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredAcrMismatch)
+        ));
+        let claims = Claims::create(Duration::from_secs(10));
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredAcrMissing)
+        ));
+        options.required_amr = Some(HashSet::new());
+        options.required_acr = None;
+        assert!(matches!(
+            claims
+                .validate(&options)
+                .err()
+                .unwrap()
+                .downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredAmrMissing)
+        ));
+    }
+
+    #[test]
+    fn expiration_for() {
+        let now = Duration::from_millis(1_600_000_000_999);
+        let valid_for = Duration::from_millis(3_600_500);
+        let expires_at = Claims::expiration_for(valid_for, now);
+        assert_eq!(expires_at, Duration::from_secs(1_600_003_600));
+        assert_eq!(
+            Claims::create_with_time(now, valid_for).expires_at,
+            Some(expires_at)
+        );
+    }
+
+    #[test]
+    fn required_and_allowed_audiences() {
+        let options = VerificationOptions::default()
+            .with_required_audiences(["service"])
+            .with_allowed_audiences(["service", "extra"]);
+        let audiences = |audiences: &[&str]| {
+            Claims::create(Duration::from_mins(10)).with_audiences_iter(audiences.iter())
+        };
+        assert!(audiences(&["service"]).validate(&options).is_ok());
+        assert!(audiences(&["service", "extra"]).validate(&options).is_ok());
+        let res = audiences(&["extra"]).validate(&options);
+        assert!(matches!(
+            res.err().unwrap().downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredAudienceMismatch)
+        ));
+        let res = audiences(&["service", "unknown"]).validate(&options);
+        assert!(matches!(
+            res.err().unwrap().downcast_ref::<JWTError>(),
+            Some(JWTError::AudienceNotAllowed)
+        ));
+        let res = Claims::create(Duration::from_mins(10)).validate(&options);
+        assert!(matches!(
+            res.err().unwrap().downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredAudienceMissing)
+        ));
+
+        let claims = Claims::create(Duration::from_mins(10)).with_audience("service,unknown");
+        let options = VerificationOptions {
+            audience_delimiters: vec![','],
+            ..options
+        };
+        assert!(claims.validate(&options).is_err());
+        let claims = Claims::create(Duration::from_mins(10)).with_audience("service,extra");
+        assert!(claims.validate(&options).is_ok());
+
+        let options = VerificationOptions {
+            required_audiences: None,
+            ..options
+        };
+        assert!(Claims::create(Duration::from_mins(10))
+            .validate(&options)
+            .is_ok());
+    }
+
+    #[test]
+    fn should_update_claims_in_place() {
+        let mut claims = Claims::create(Duration::from_mins(10))
+            .with_issued_at(UnixTimeStamp::from_secs(1_000))
+            .with_invalid_before(UnixTimeStamp::from_secs(1_000))
+            .with_subject("subject");
+        claims
+            .set_issued_at(UnixTimeStamp::from_secs(2_000))
+            .set_expires_at(UnixTimeStamp::from_secs(3_000))
+            .set_jwt_id("jti");
+        assert_eq!(claims.issued_at, Some(UnixTimeStamp::from_secs(2_000)));
+        assert_eq!(claims.expires_at, Some(UnixTimeStamp::from_secs(3_000)));
+        assert_eq!(claims.invalid_before, Some(UnixTimeStamp::from_secs(1_000)));
+        assert_eq!(claims.jwt_id.as_deref(), Some("jti"));
+
+        claims.touch(Duration::from_mins(5));
+        let issued_at = claims.issued_at.unwrap();
+        assert!(issued_at > UnixTimeStamp::from_secs(2_000));
+        assert_eq!(claims.invalid_before, Some(issued_at));
+        assert_eq!(
+            claims.expires_at,
+            Some(Claims::expiration_for(Duration::from_mins(5), issued_at))
+        );
+        assert_eq!(claims.subject.as_deref(), Some("subject"));
+        assert_eq!(claims.jwt_id.as_deref(), Some("jti"));
+    }
+
+    #[test]
+    fn should_split_string_audiences() {
+        let claims = Claims::create(Duration::from_mins(10)).with_audience("a b,c");
+        let options = VerificationOptions {
+            required_audience: Some("b".to_string()),
+            ..Default::default()
+        };
+        assert!(claims.validate(&options).is_err());
+        let options = VerificationOptions {
+            audience_delimiters: vec![' ', ','],
+            ..options
+        };
+        assert!(claims.validate(&options).is_ok());
+        let options = VerificationOptions {
+            required_audience: Some("c".to_string()),
+            ..options
+        };
+        assert!(claims.validate(&options).is_ok());
+        let options = VerificationOptions {
+            required_audience: Some("a b".to_string()),
+            ..options
+        };
+        assert!(claims.validate(&options).is_err());
+    }
+
+    #[test]
+    fn should_chain_time_builders() {
+        let claims = Claims::create(Duration::from_mins(10))
+            .with_issued_at(UnixTimeStamp::from_secs(1_000))
+            .with_invalid_before(UnixTimeStamp::from_secs(2_000))
+            .with_expires_at(UnixTimeStamp::from_secs(3_000));
+        assert_eq!(claims.issued_at, Some(UnixTimeStamp::from_secs(1_000)));
+        assert_eq!(claims.invalid_before, Some(UnixTimeStamp::from_secs(2_000)));
+        assert_eq!(claims.expires_at, Some(UnixTimeStamp::from_secs(3_000)));
+        let claims = claims.without_expiry().without_not_before();
+        assert!(claims.expires_at.is_none());
+        assert!(claims.invalid_before.is_none());
+        let json = serde_json::to_string(&claims).unwrap();
+        assert!(!json.contains("nbf"));
+    }
+
+    #[test]
+    fn should_set_audiences_from_iter() {
+        let claims =
+            Claims::create(Duration::from_mins(10)).with_audiences_iter(vec!["a", "b", "a"]);
+        let mut audiences = HashSet::new();
+        audiences.insert("a".to_string());
+        audiences.insert("b".to_string());
+        assert_eq!(claims.audiences, Some(Audiences::AsSet(audiences)));
+    }
+    #[test]
+    fn should_truncate_timestamps() {
+        let claims = Claims::create(Duration::from_millis(1500));
+        let issued_at = claims.issued_at.unwrap();
+        let expires_at = claims.expires_at.unwrap();
+        assert_eq!(issued_at.subsec_nanos(), 0);
+        assert_eq!(expires_at.subsec_nanos(), 0);
+        assert_eq!(expires_at - issued_at, Duration::from_secs(1));
+        assert_eq!(claims.invalid_before, claims.issued_at);
+    }
+    #[test]
+    fn should_collect_all_validation_errors() {
+        let claims = Claims::create(Duration::from_mins(10)).with_issuer("issuer");
+        let options = VerificationOptions {
+            required_issuer: Some("other issuer".to_string()),
+            required_audience: Some("audience".to_string()),
+            ..Default::default()
+        };
+        let errors = claims.validate_collect(&options).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], JWTError::RequiredIssuerMismatch));
+        assert!(matches!(errors[1], JWTError::RequiredAudienceMissing));
+        assert!(claims.validate(&options).is_err());
+
+        assert!(claims
+            .validate_collect(&VerificationOptions::default())
+            .is_ok());
+    }
+    #[test]
+    fn should_require_expiry() {
+        let mut claims = Claims::create(Duration::from_mins(10));
+        let options = VerificationOptions::strict_expiry();
+        assert!(claims.validate(&options).is_ok());
+        claims.expires_at = None;
+        assert!(claims.validate(&VerificationOptions::default()).is_ok());
+        let errors = claims.validate_collect(&options).unwrap_err();
+        assert!(matches!(errors[0], JWTError::MissingExpiry));
+        claims.expires_at = Some(UnixTimeStamp::from_secs(1));
+        let errors = claims.validate_collect(&options).unwrap_err();
+        assert!(matches!(errors[0], JWTError::TokenHasExpired));
+    }
+    #[cfg(feature = "vc")]
+    #[test]
+    fn should_round_trip_verifiable_credentials() {
+        let credential = serde_json::json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "type": ["VerifiableCredential", "UniversityDegreeCredential"],
+            "credentialSubject": { "degree": { "type": "BachelorDegree" } }
+        });
+        let claims = Claims::with_custom_claims(
+            VerifiableCredentialClaims::with_credential(credential),
+            Duration::from_mins(10),
+        )
+        .with_issuer("issuer");
+        let json = serde_json::to_string(&claims).unwrap();
+        let decoded: JWTClaims<VerifiableCredentialClaims> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.issuer.as_deref(), Some("issuer"));
+        assert_eq!(
+            decoded.custom.credential_types(),
+            vec!["VerifiableCredential", "UniversityDegreeCredential"]
+        );
+        assert_eq!(
+            decoded.custom.credential_subject().unwrap()["degree"]["type"],
+            "BachelorDegree"
+        );
+        assert!(decoded.custom.presentation.is_none());
+    }
+
+    #[test]
+    fn should_check_auth_time() {
+        let claims = Claims::create(Duration::from_mins(10));
+        let options = VerificationOptions {
+            max_auth_age: Some(Duration::from_mins(5)),
+            ..Default::default()
+        };
+        let errors = claims.validate_collect(&options).unwrap_err();
+        assert!(matches!(errors[..], [JWTError::AuthTimeMissing]));
+        let claims = claims.with_auth_time(Clock::now_since_epoch() - Duration::from_mins(10));
+        let errors = claims.validate_collect(&options).unwrap_err();
+        assert!(matches!(errors[..], [JWTError::AuthTooOld]));
+        let claims = claims.with_auth_time(Clock::now_since_epoch());
+        assert!(claims.validate(&options).is_ok());
+        let json = serde_json::to_string(&claims).unwrap();
+        assert!(json.contains("\"auth_time\":"));
+    }
+
+    #[test]
+    fn should_accept_expired_tokens() {
+        let mut claims = Claims::create(Duration::from_mins(10));
+        claims.issued_at = Some(Clock::now_since_epoch() - Duration::from_hours(2));
+        claims.expires_at = Some(Clock::now_since_epoch() - Duration::from_hours(1));
+        assert!(claims.is_expired());
+        assert!(claims.validate(&VerificationOptions::default()).is_err());
+        let options = VerificationOptions {
+            accept_expired: true,
+            ..Default::default()
+        };
+        assert!(claims.validate(&options).is_ok());
+        let options = VerificationOptions {
+            expired_grace: Some(Duration::from_hours(24)),
+            ..options
+        };
+        assert!(claims.validate(&options).is_ok());
+        let options = VerificationOptions {
+            expired_grace: Some(Duration::from_mins(30)),
+            ..options
+        };
+        assert!(claims.validate(&options).is_err());
+        assert!(!Claims::create(Duration::from_mins(10)).is_expired());
+    }
+
+    #[test]
+    fn should_reject_inconsistent_time_claims() {
+        let mut claims = Claims::create(Duration::from_mins(10));
+        claims.issued_at = claims.expires_at.map(|x| x + Duration::from_secs(1));
+        let options = VerificationOptions {
+            time_tolerance: Some(Duration::from_mins(15)),
+            ..Default::default()
+        };
+        let errors = claims.validate_collect(&options).unwrap_err();
+        assert!(matches!(errors[..], [JWTError::InconsistentTimeClaims]));
+        let options = VerificationOptions {
+            allow_inconsistent_time_claims: true,
+            ..options
+        };
+        assert!(claims.validate(&options).is_ok());
+    }
+    #[test]
+    fn should_reject_out_of_range_timestamps() {
+        for exp in &[
+            "-1",
+            "-9223372036854775808",
+            "9223372036854775807",
+            "18446744073709551615",
+            "99999999999999999999",
+        ] {
+            let json = format!(r#"{{"exp":{}}}"#, exp);
+            assert!(serde_json::from_str::<JWTClaims<NoCustomClaims>>(&json).is_err());
+        }
+        let json = format!(r#"{{"exp":{}}}"#, u32::MAX);
+        let claims: JWTClaims<NoCustomClaims> = serde_json::from_str(&json).unwrap();
+        let options = VerificationOptions {
+            accept_expired: true,
+            expired_grace: Some(Duration::from_secs(u32::MAX as _)),
+            time_tolerance: Some(Duration::from_secs(u32::MAX as _)),
+            ..Default::default()
+        };
+        claims.validate(&options).unwrap();
+        let claims = claims.with_issued_at(Duration::from_secs(u32::MAX as _));
+        claims.validate(&options).unwrap();
+    }
+
+    #[test]
+    fn should_create_claims_at_a_given_time() {
+        let now = UnixTimeStamp::from_millis(1_600_000_000_500);
+        let claims = Claims::create_with_time(now, Duration::from_hours(1));
+        assert_eq!(
+            claims.issued_at,
+            Some(UnixTimeStamp::from_secs(1_600_000_000))
+        );
+        assert_eq!(claims.invalid_before, claims.issued_at);
+        assert_eq!(
+            claims.expires_at,
+            Some(UnixTimeStamp::from_secs(1_600_003_600))
+        );
+        let claims = Claims::with_custom_claims_at(NoCustomClaims {}, now, Duration::from_hours(1));
+        assert_eq!(
+            claims.issued_at,
+            Some(UnixTimeStamp::from_secs(1_600_000_000))
+        );
+    }
+
+    #[test]
+    fn should_compute_oidc_hashes() {
+        // Example from the OpenID Connect Core specification, appendix A.3
+        let access_token = "jHkWEdUXMU1BwAsC4vtUsZwnNvTIxEl0z9K3vx5KF0Y";
+        let claims = Claims::create(Duration::from_mins(10))
+            .with_access_token_hash(access_token, "RS256")
+            .unwrap();
+        assert_eq!(
+            claims.access_token_hash.as_deref(),
+            Some("77QmUPtjPfzWtF2AnpK9RQ")
+        );
+        claims
+            .verify_access_token_hash(access_token, "RS256")
+            .unwrap();
+        assert!(claims
+            .verify_access_token_hash("other token", "RS256")
+            .is_err());
+        assert!(claims.verify_code_hash("code", "RS256").is_err());
+
+        let claims = claims.with_code_hash("code", "EdDSA").unwrap();
+        claims.verify_code_hash("code", "EdDSA").unwrap();
+        assert!(claims.with_code_hash("code", "none").is_err());
+    }
 }
@@ -1,12 +1,27 @@
-use coarsetime::{Duration, UnixTimeStamp};
+use coarsetime::{Clock, Duration, UnixTimeStamp};
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::{BitOr, BitOrAssign};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::claims::DEFAULT_TIME_TOLERANCE_SECS;
+use crate::error::{ConfigError, KeyImportErrorReason};
 
 /// Additional features to enable during verification
-#[derive(Clone, Debug, Default)]
+///
+/// Options can be loaded from configuration files. Dates and durations are represented as a number of seconds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VerificationOptions {
     /// Reject tokens created before the given date
     ///
     /// For a given user, the time of the last successful authentication can be kept in a database,
     /// and `reject_before` can then be used to reject older (replayed) tokens.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::unix_timestamp"
+    )]
     pub reject_before: Option<UnixTimeStamp>,
 
     /// Accept tokens created with a date in the future
@@ -15,30 +30,690 @@ pub struct VerificationOptions {
     /// Require a specific issuer to be present
     pub required_issuer: Option<String>,
 
+    /// Canonicalize the issuer and `required_issuer` before comparing them
+    ///
+    /// The scheme and host of URLs are lowercased, and a single trailing slash is removed, so that
+    /// `https://Issuer.example.com/` matches `https://issuer.example.com`. By default, issuers must
+    /// be strictly equal.
+    pub canonicalize_issuer: bool,
+
     /// Require a specific subject to be present
     pub required_subject: Option<String>,
 
+    /// Require the subject to be present, and to be one of the given subjects
+    ///
+    /// This can be combined with `required_subject`, in which case both must match.
+    pub allowed_subjects: Option<HashSet<String>>,
+
     /// Require a specific key identifierto be present
     pub required_key_id: Option<String>,
 
+    /// Require a key identifier to be present, no matter what its value is
+    pub require_key_id: bool,
+
     /// Require a specific public key to be present
     pub required_public_key: Option<String>,
 
     /// Require a specific nonce to be present
     pub required_nonce: Option<String>,
 
+    /// Require the authentication methods (`amr`) to include all the given methods, such as `"mfa"`
+    pub required_amr: Option<HashSet<String>>,
+
+    /// Require a specific authentication context class (`acr`) to be present
+    pub required_acr: Option<String>,
+
+    /// Reject tokens whose `auth_time` is older than the given duration
+    ///
+    /// Tokens without an `auth_time` claim are also rejected.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::duration"
+    )]
+    pub max_auth_age: Option<Duration>,
+
     /// Require a specific audience to be present
     pub required_audience: Option<String>,
 
+    /// Require all the given audiences to be present
+    ///
+    /// This can be combined with `allowed_audiences`: the token must then include every
+    /// required audience, and nothing outside of the allowed audiences.
+    pub required_audiences: Option<HashSet<String>>,
+
+    /// Reject tokens with audiences that are not in the given set
+    ///
+    /// Tokens without audiences are accepted, unless `required_audience` or
+    /// `required_audiences` is also set.
+    pub allowed_audiences: Option<HashSet<String>>,
+
+    /// Characters separating multiple audiences in a string audience
+    ///
+    /// Some systems encode a set of audiences as a single string, such as `"a b c"`.
+    /// With `audience_delimiters` set to `[' ', ',']`, such a string is split before
+    /// checking the required and allowed audiences. By default, string audiences are not split.
+    pub audience_delimiters: Vec<char>,
+
+    /// Accept tokens that have expired
+    ///
+    /// Unlike `time_tolerance`, this only affects the expiration date. If `expired_grace` is set,
+    /// tokens that expired longer ago than that are still rejected. `JWTClaims::is_expired()`
+    /// tells whether a token has been accepted despite being expired.
+    pub accept_expired: bool,
+
+    /// Maximum time since expiration for tokens to be accepted, if `accept_expired` is set
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::duration"
+    )]
+    pub expired_grace: Option<Duration>,
+
     /// Time tolerance for validating expiration dates
+    ///
+    /// If not set, `DEFAULT_TIME_TOLERANCE_SECS` is used.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::duration"
+    )]
     pub time_tolerance: Option<Duration>,
 
     /// Reject tokens created more than `max_validity` ago
+    ///
+    /// The creation date is the one given by `max_validity_anchor`.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::duration"
+    )]
     pub max_validity: Option<Duration>,
+
+    /// The time claim used to compute the age of a token, that `max_validity` applies to
+    pub max_validity_anchor: TokenAgeAnchor,
+
+    /// Reject tokens that don't include an expiration date
+    pub require_expiry: bool,
+
+    /// Reject tokens with custom claims that look like standard claims
+    ///
+    /// This catches issuers using names such as `EXP` or `expires` instead of `exp`.
+    /// Duplicated standard claims are always rejected.
+    pub reject_reserved_claim_collisions: bool,
+
+    /// Ignore non-string entries of the audience set instead of rejecting the token
+    ///
+    /// This is a compatibility shim for issuers that don't follow the specification,
+    /// for example by representing audiences as objects. Only string audiences are kept.
+    pub lenient_audiences: bool,
+
+    /// Accept segments encoded with trailing `=` padding
+    ///
+    /// This is a compatibility shim for issuers that pad base64url-encoded segments, which the
+    /// specification forbids. Tokens created by this crate are never padded.
+    pub accept_padded_segments: bool,
+
+    /// Accept time claims represented as RFC 3339 strings in addition to numbers
+    ///
+    /// This is not compliant with the JWT specification, and should only be used for tokens
+    /// exchanged between cooperating services.
+    ///
+    /// This requires the `chrono` feature. Without it, verification fails with
+    /// `JWTError::RFC3339TimestampsUnsupported`.
+    pub rfc3339_timestamps: bool,
+
+    /// Accept tokens whose creation date is after their expiration date
+    ///
+    /// By default, such tokens are rejected with `JWTError::InconsistentTimeClaims`.
+    pub allow_inconsistent_time_claims: bool,
+
+    /// Standard claims that must be present, no matter what their value is
+    ///
+    /// In configuration files, this is a list of claim names, such as `["sub", "aud"]`.
+    pub required_claims: RequiredClaims,
 }
 
+/// The time claim used as the creation date of a token, to compute its age
+///
+/// In configuration files, this is `"issued_at"`, `"issued_at_or_invalid_before"` or `"invalid_before"`.
+/// Tokens without the selected claims are not subject to `max_validity`; use `required_claims` to reject them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenAgeAnchor {
+    /// The `iat` claim
+    #[default]
+    IssuedAt,
+    /// The `iat` claim, or the `nbf` claim if `iat` is absent
+    IssuedAtOrInvalidBefore,
+    /// The `nbf` claim, that is the activation time of the token
+    InvalidBefore,
+}
+
+/// A set of standard claims that must be present in a token
+///
+/// Sets can be combined with `|`, for example `RequiredClaims::SUBJECT | RequiredClaims::AUDIENCES`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RequiredClaims(u16);
+
+impl RequiredClaims {
+    /// No claims
+    pub const NONE: Self = RequiredClaims(0);
+    /// `iat`
+    pub const ISSUED_AT: Self = RequiredClaims(1 << 0);
+    /// `exp`
+    pub const EXPIRES_AT: Self = RequiredClaims(1 << 1);
+    /// `nbf`
+    pub const INVALID_BEFORE: Self = RequiredClaims(1 << 2);
+    /// `iss`
+    pub const ISSUER: Self = RequiredClaims(1 << 3);
+    /// `sub`
+    pub const SUBJECT: Self = RequiredClaims(1 << 4);
+    /// `aud`
+    pub const AUDIENCES: Self = RequiredClaims(1 << 5);
+    /// `jti`
+    pub const JWT_ID: Self = RequiredClaims(1 << 6);
+    /// `nonce`
+    pub const NONCE: Self = RequiredClaims(1 << 7);
+    /// `auth_time`
+    pub const AUTH_TIME: Self = RequiredClaims(1 << 8);
+
+    const NAMES: &'static [(RequiredClaims, &'static str)] = &[
+        (Self::ISSUED_AT, "iat"),
+        (Self::EXPIRES_AT, "exp"),
+        (Self::INVALID_BEFORE, "nbf"),
+        (Self::ISSUER, "iss"),
+        (Self::SUBJECT, "sub"),
+        (Self::AUDIENCES, "aud"),
+        (Self::JWT_ID, "jti"),
+        (Self::NONCE, "nonce"),
+        (Self::AUTH_TIME, "auth_time"),
+    ];
+
+    /// Return `true` if all the claims of `other` are in the set
+    pub fn contains(self, other: RequiredClaims) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Return `true` if the set is empty
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return the names of the claims in the set
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(claim, _)| self.contains(*claim))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// Return the set containing the claim called `name`, if it is a standard claim
+    pub fn from_name(name: &str) -> Option<RequiredClaims> {
+        Self::NAMES
+            .iter()
+            .find(|(_, x)| *x == name)
+            .map(|(claim, _)| *claim)
+    }
+}
+
+impl BitOr for RequiredClaims {
+    type Output = RequiredClaims;
+
+    fn bitor(self, other: RequiredClaims) -> RequiredClaims {
+        RequiredClaims(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for RequiredClaims {
+    fn bitor_assign(&mut self, other: RequiredClaims) {
+        self.0 |= other.0;
+    }
+}
+
+impl Serialize for RequiredClaims {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+impl<'de> Deserialize<'de> for RequiredClaims {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names: Vec<String> = Deserialize::deserialize(deserializer)?;
+        let mut required_claims = RequiredClaims::NONE;
+        for name in names {
+            required_claims |= RequiredClaims::from_name(&name)
+                .ok_or_else(|| DeError::custom(format!("Unknown standard claim: [{}]", name)))?;
+        }
+        Ok(required_claims)
+    }
+}
+
+impl VerificationOptions {
+    /// Default options, with a different time tolerance
+    ///
+    /// This can be used as a base for all the options of an application, instead
+    /// of `VerificationOptions::default()`, that uses a 900 seconds tolerance.
+    pub fn with_time_tolerance(time_tolerance: Duration) -> Self {
+        VerificationOptions {
+            time_tolerance: Some(time_tolerance),
+            ..Default::default()
+        }
+    }
+
+    /// The time tolerance that will be used, taking the default value into account
+    pub fn effective_time_tolerance(&self) -> Duration {
+        self.time_tolerance
+            .unwrap_or_else(|| Duration::from_secs(DEFAULT_TIME_TOLERANCE_SECS))
+    }
+
+    /// Check that the options are consistent
+    ///
+    /// Options that would reject almost every token, or settings that have no effect because
+    /// of other settings, are reported. This is meant to be called once, at startup.
+    pub fn validate_config(&self) -> Result<(), ConfigError> {
+        let zero = Duration::from_secs(0);
+        if self.max_validity == Some(zero) {
+            return Err(ConfigError::ZeroMaxValidity);
+        }
+        if self.max_auth_age == Some(zero) {
+            return Err(ConfigError::ZeroMaxAuthAge);
+        }
+        if self.expired_grace.is_some() && !self.accept_expired {
+            return Err(ConfigError::ExpiredGraceWithoutAcceptExpired);
+        }
+        if !self.audience_delimiters.is_empty()
+            && self.required_audience.is_none()
+            && self.required_audiences.is_none()
+            && self.allowed_audiences.is_none()
+        {
+            return Err(ConfigError::AudienceDelimitersWithoutRequiredAudience);
+        }
+        if let Some(allowed_audiences) = &self.allowed_audiences {
+            let is_allowed = |audience: &String| allowed_audiences.contains(audience);
+            if !self.required_audience.iter().all(is_allowed)
+                || !self.required_audiences.iter().flatten().all(is_allowed)
+            {
+                return Err(ConfigError::RequiredAudienceNotAllowed);
+            }
+        }
+        if let (Some(allowed_subjects), Some(required_subject)) =
+            (&self.allowed_subjects, &self.required_subject)
+        {
+            if !allowed_subjects.contains(required_subject) {
+                return Err(ConfigError::RequiredSubjectNotAllowed);
+            }
+        }
+        if let Some(reject_before) = self.reject_before {
+            if reject_before > Clock::now_since_epoch() {
+                return Err(ConfigError::RejectBeforeInFuture);
+            }
+        }
+        Ok(())
+    }
+
+    /// Require a specific issuer to be present
+    ///
+    /// Like the other `with_required_*` setters, this accepts any value that can be converted
+    /// into a string, such as application-specific newtypes.
+    #[must_use]
+    pub fn with_required_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.required_issuer = Some(issuer.into());
+        self
+    }
+
+    /// Require a specific subject to be present
+    #[must_use]
+    pub fn with_required_subject(mut self, subject: impl Into<String>) -> Self {
+        self.required_subject = Some(subject.into());
+        self
+    }
+
+    /// Require the subject to be present, and to be one of the given subjects
+    #[must_use]
+    pub fn with_allowed_subjects(
+        mut self,
+        subjects: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.allowed_subjects = Some(subjects.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Require a specific audience to be present
+    #[must_use]
+    pub fn with_required_audience(mut self, audience: impl Into<String>) -> Self {
+        self.required_audience = Some(audience.into());
+        self
+    }
+
+    /// Require all the given audiences to be present
+    #[must_use]
+    pub fn with_required_audiences(
+        mut self,
+        audiences: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.required_audiences = Some(audiences.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Reject tokens with audiences outside of the given ones
+    #[must_use]
+    pub fn with_allowed_audiences(
+        mut self,
+        audiences: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.allowed_audiences = Some(audiences.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Require a specific key identifier to be present
+    #[must_use]
+    pub fn with_required_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.required_key_id = Some(key_id.into());
+        self
+    }
+
+    /// Require a specific nonce to be present
+    #[must_use]
+    pub fn with_required_nonce(mut self, nonce: impl Into<String>) -> Self {
+        self.required_nonce = Some(nonce.into());
+        self
+    }
+
+    /// Options requiring tokens to have an expiration date, that must not have passed
+    ///
+    /// Tokens without an `exp` claim are rejected with `JWTError::MissingExpiry`, and tokens
+    /// that expired more than `time_tolerance` ago are rejected with `JWTError::TokenHasExpired`.
+    pub fn strict_expiry() -> Self {
+        VerificationOptions {
+            require_expiry: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Types that can be used as absolute dates in claims
+pub trait ToUnixTimeStamp {
+    /// Convert the date to a UNIX timestamp
+    ///
+    /// Dates before the UNIX epoch are mapped to the epoch.
+    fn to_unix_timestamp(&self) -> UnixTimeStamp;
+}
+
+impl ToUnixTimeStamp for UnixTimeStamp {
+    fn to_unix_timestamp(&self) -> UnixTimeStamp {
+        *self
+    }
+}
+
+impl ToUnixTimeStamp for SystemTime {
+    fn to_unix_timestamp(&self) -> UnixTimeStamp {
+        self.duration_since(UNIX_EPOCH).unwrap_or_default().into()
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToUnixTimeStamp for chrono::DateTime<chrono::Utc> {
+    fn to_unix_timestamp(&self) -> UnixTimeStamp {
+        SystemTime::from(*self).to_unix_timestamp()
+    }
+}
+
+/// Convert a UNIX timestamp into a `SystemTime`
+pub fn unix_timestamp_to_system_time(unix_timestamp: UnixTimeStamp) -> SystemTime {
+    UNIX_EPOCH + std::time::Duration::from(unix_timestamp)
+}
+
+/// Convert a UNIX timestamp into a `chrono` date
+#[cfg(feature = "chrono")]
+pub fn unix_timestamp_to_chrono(unix_timestamp: UnixTimeStamp) -> chrono::DateTime<chrono::Utc> {
+    unix_timestamp_to_system_time(unix_timestamp).into()
+}
+
+/// Compare two byte strings in constant time
+///
+/// Only the content is secret: the lengths are checked first, with an early return, so that
+/// strings of different lengths are immediately considered different.
 #[inline(never)]
 pub(crate) fn timingsafe_eq(a: &[u8], b: &[u8]) -> bool {
-    assert_eq!(a.len(), b.len());
+    if a.len() != b.len() {
+        return false;
+    }
     a.iter().zip(b.iter()).fold(0, |c, (x, y)| c | (x ^ y)) == 0
 }
+
+/// Compute the RFC 7638 thumbprint of a JWK, given its required members
+///
+/// The members are serialized in lexicographic order, without whitespace, and hashed with SHA-256.
+pub(crate) fn jwk_thumbprint(members: &[(&str, &str)]) -> String {
+    let members: BTreeMap<_, _> = members.iter().cloned().collect();
+    let json = serde_json::to_string(&members).unwrap();
+    Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(json.as_bytes())).unwrap()
+}
+
+/// Rewrite a PEM document with canonical line wrapping.
+///
+/// Line endings, line lengths and leading/trailing whitespace vary depending on the tool
+/// that exported a key. The base64 content is re-wrapped at 64 columns, with LF line endings.
+/// Input that doesn't look like PEM is returned as-is, so that the parser can report the error.
+pub(crate) fn normalize_pem(pem: &str) -> String {
+    let pem = pem.trim();
+    let label = match pem
+        .strip_prefix("-----BEGIN ")
+        .and_then(|x| x.split("-----").next())
+    {
+        Some(label) => label,
+        None => return pem.to_string(),
+    };
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let body = match pem
+        .strip_prefix(&begin)
+        .and_then(|x| x.trim_end().strip_suffix(&end))
+    {
+        Some(body) => body,
+        None => return pem.to_string(),
+    };
+    let body: Vec<u8> = body.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    let mut normalized = begin;
+    normalized.push('\n');
+    for line in body.chunks(64) {
+        normalized.push_str(&String::from_utf8_lossy(line));
+        normalized.push('\n');
+    }
+    normalized.push_str(&end);
+    normalized.push('\n');
+    normalized
+}
+
+#[test]
+fn verification_options_from_config() {
+    let config = r#"{"required_issuer": "issuer", "max_validity": 3600, "accept_future": true}"#;
+    let options: VerificationOptions = serde_json::from_str(config).unwrap();
+    assert_eq!(options.required_issuer.as_deref(), Some("issuer"));
+    assert_eq!(options.max_validity, Some(Duration::from_hours(1)));
+    assert!(options.accept_future);
+    assert!(options.time_tolerance.is_none());
+    assert_eq!(options.effective_time_tolerance(), Duration::from_secs(900));
+
+    let serialized = serde_json::to_string(&options).unwrap();
+    let options: VerificationOptions = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(options.max_validity, Some(Duration::from_hours(1)));
+}
+
+#[test]
+fn verification_options_with_time_tolerance() {
+    let options = VerificationOptions::with_time_tolerance(Duration::from_secs(30));
+    assert_eq!(options.effective_time_tolerance(), Duration::from_secs(30));
+    let options = VerificationOptions {
+        required_issuer: Some("issuer".to_string()),
+        ..options
+    };
+    assert_eq!(options.time_tolerance, Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn unix_timestamp_conversions() {
+    let system_time = UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    let unix_timestamp = system_time.to_unix_timestamp();
+    assert_eq!(unix_timestamp, UnixTimeStamp::from_secs(1_600_000_000));
+    assert_eq!(unix_timestamp_to_system_time(unix_timestamp), system_time);
+    let before_epoch = UNIX_EPOCH - std::time::Duration::from_secs(1);
+    assert_eq!(
+        before_epoch.to_unix_timestamp(),
+        UnixTimeStamp::from_secs(0)
+    );
+
+    #[cfg(feature = "chrono")]
+    {
+        let date = unix_timestamp_to_chrono(unix_timestamp);
+        assert_eq!(date.timestamp(), 1_600_000_000);
+        assert_eq!(date.to_unix_timestamp(), unix_timestamp);
+    }
+}
+
+/// Guess why a SEC1-encoded elliptic curve public key on a 256-bit curve couldn't be imported
+pub(crate) fn ec_public_key_import_error_reason(raw: &[u8]) -> KeyImportErrorReason {
+    // Encoded point sizes for 256, 384 and 521-bit curves
+    let (expected_len, other_curves_lens) = match raw.first() {
+        Some(0x02) | Some(0x03) => (33, [49, 67]),
+        Some(0x04) => (65, [97, 133]),
+        Some(_) => return KeyImportErrorReason::Invalid,
+        None => return KeyImportErrorReason::Truncated,
+    };
+    if raw.len() < expected_len {
+        KeyImportErrorReason::Truncated
+    } else if other_curves_lens.contains(&raw.len()) {
+        KeyImportErrorReason::UnsupportedCurve
+    } else {
+        KeyImportErrorReason::Invalid
+    }
+}
+
+/// Guess why an elliptic curve secret key on a 256-bit curve couldn't be imported
+pub(crate) fn ec_key_pair_import_error_reason(raw: &[u8]) -> KeyImportErrorReason {
+    match raw.len() {
+        0..=31 => KeyImportErrorReason::Truncated,
+        48 | 66 => KeyImportErrorReason::UnsupportedCurve,
+        _ => KeyImportErrorReason::Invalid,
+    }
+}
+
+#[test]
+fn verification_options_validate_config() {
+    assert!(VerificationOptions::default().validate_config().is_ok());
+    assert!(VerificationOptions::strict_expiry()
+        .validate_config()
+        .is_ok());
+    let options = VerificationOptions {
+        max_validity: Some(Duration::from_secs(0)),
+        ..Default::default()
+    };
+    assert_eq!(options.validate_config(), Err(ConfigError::ZeroMaxValidity));
+    let options = VerificationOptions {
+        expired_grace: Some(Duration::from_mins(5)),
+        ..Default::default()
+    };
+    assert_eq!(
+        options.validate_config(),
+        Err(ConfigError::ExpiredGraceWithoutAcceptExpired)
+    );
+    let options = VerificationOptions {
+        accept_expired: true,
+        ..options
+    };
+    assert!(options.validate_config().is_ok());
+    let options = VerificationOptions {
+        audience_delimiters: vec![' '],
+        ..Default::default()
+    };
+    assert_eq!(
+        options.validate_config(),
+        Err(ConfigError::AudienceDelimitersWithoutRequiredAudience)
+    );
+    let options = VerificationOptions {
+        required_audiences: Some(["a".to_string(), "b".to_string()].into()),
+        allowed_audiences: Some(["a".to_string(), "c".to_string()].into()),
+        ..options
+    };
+    assert_eq!(
+        options.validate_config(),
+        Err(ConfigError::RequiredAudienceNotAllowed)
+    );
+    let options = VerificationOptions {
+        required_audiences: Some(["a".to_string()].into()),
+        ..options
+    };
+    assert!(options.validate_config().is_ok());
+    let options = VerificationOptions::default()
+        .with_required_subject("a")
+        .with_allowed_subjects(["b", "c"]);
+    assert_eq!(
+        options.validate_config(),
+        Err(ConfigError::RequiredSubjectNotAllowed)
+    );
+    let options = options.with_allowed_subjects(["a", "b"]);
+    assert!(options.validate_config().is_ok());
+    let options = VerificationOptions {
+        reject_before: Some(Clock::now_since_epoch() + Duration::from_hours(1)),
+        ..Default::default()
+    };
+    assert_eq!(
+        options.validate_config(),
+        Err(ConfigError::RejectBeforeInFuture)
+    );
+    let options = VerificationOptions {
+        reject_before: Some(Clock::now_since_epoch() - Duration::from_hours(1)),
+        ..Default::default()
+    };
+    assert!(options.validate_config().is_ok());
+}
+
+#[test]
+fn required_claims_from_config() {
+    let config = r#"{"required_claims": ["sub", "aud"]}"#;
+    let options: VerificationOptions = serde_json::from_str(config).unwrap();
+    assert_eq!(
+        options.required_claims,
+        RequiredClaims::SUBJECT | RequiredClaims::AUDIENCES
+    );
+    assert!(options.required_claims.contains(RequiredClaims::SUBJECT));
+    assert!(!options.required_claims.contains(RequiredClaims::ISSUER));
+    let serialized = serde_json::to_string(&options.required_claims).unwrap();
+    assert_eq!(serialized, r#"["sub","aud"]"#);
+    let config = r#"{"required_claims": ["subject"]}"#;
+    assert!(serde_json::from_str::<VerificationOptions>(config).is_err());
+}
+
+#[test]
+fn verification_options_setters() {
+    struct IssuerUrl(&'static str);
+
+    impl From<IssuerUrl> for String {
+        fn from(issuer: IssuerUrl) -> String {
+            issuer.0.to_string()
+        }
+    }
+
+    let options = VerificationOptions::default()
+        .with_required_issuer(IssuerUrl("https://issuer.example.com"))
+        .with_required_subject("subject")
+        .with_allowed_subjects(vec!["subject", "other"])
+        .with_required_audience(String::from("audience"));
+    assert_eq!(
+        options.required_issuer.as_deref(),
+        Some("https://issuer.example.com")
+    );
+    assert_eq!(options.required_subject.as_deref(), Some("subject"));
+    assert_eq!(options.allowed_subjects.unwrap().len(), 2);
+    assert_eq!(options.required_audience.as_deref(), Some("audience"));
+}
+
+#[test]
+fn token_age_anchor_from_config() {
+    let config = r#"{"max_validity": 60, "max_validity_anchor": "issued_at_or_invalid_before"}"#;
+    let options: VerificationOptions = serde_json::from_str(config).unwrap();
+    assert_eq!(
+        options.max_validity_anchor,
+        TokenAgeAnchor::IssuedAtOrInvalidBefore
+    );
+    let options: VerificationOptions = serde_json::from_str("{}").unwrap();
+    assert_eq!(options.max_validity_anchor, TokenAgeAnchor::IssuedAt);
+}
@@ -1,23 +1,45 @@
 pub use anyhow::{anyhow, bail, ensure, Error};
 
+/// Errors that can be returned by this crate
+///
+/// New variants may be added in minor releases. Code matching on `JWTError` must include a
+/// wildcard arm, typically mapping unknown errors to a generic rejection of the token.
+/// Existing variants are not renamed or removed outside of major releases.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum JWTError {
     #[error("Internal error: [{0}]")]
     InternalError(String),
     #[error("JWT compact encoding error")]
     CompactEncodingError,
+    #[error("Invalid token format: unexpected data after the signature")]
+    InvalidTokenFormat,
+    #[error(
+        "Not a JWS: this looks like an encrypted token (JWE), that this verifier doesn't support"
+    )]
+    NotAJws,
     #[error("JWT header too large")]
     HeaderTooLarge,
     #[error("JWT algorithm mismatch")]
     AlgorithmMismatch,
+    #[error("JWT payload encoding mismatch")]
+    PayloadEncodingMismatch,
     #[error("JWT key identifier mismatch")]
     KeyIdentifierMismatch,
     #[error("Missing JWT key identifier")]
     MissingJWTKeyIdentifier,
+    #[error("Required key identifier missing")]
+    RequiredKeyIdMissing,
+    #[error("No key available to verify the token")]
+    NoMatchingKey,
     #[error("Authentication tag didn't verify")]
     InvalidAuthenticationTag,
     #[error("Signature tag didn't verify")]
     InvalidSignature,
+    #[error("Claims could not be deserialized: {0}")]
+    ClaimsDeserialization(#[source] serde_json::Error),
+    #[error("Invalid UTF-8 in the {0} segment")]
+    InvalidUtf8(&'static str),
     #[error("Old token reused")]
     OldTokenReused,
     #[error("Clock drift detected")]
@@ -28,10 +50,28 @@ pub enum JWTError {
     TokenNotValidYet,
     #[error("Token has expired")]
     TokenHasExpired,
+    #[error("Token expiration date missing")]
+    MissingExpiry,
+    #[error("Token was created after its expiration date")]
+    InconsistentTimeClaims,
+    #[error("Time claim is negative or out of range")]
+    InvalidTimeClaim,
+    #[error("Authentication time missing")]
+    AuthTimeMissing,
+    #[error("Authentication is too old")]
+    AuthTooOld,
     #[error("Required nonce missing")]
     RequiredNonceMissing,
     #[error("Required nonce mismatch")]
     RequiredNonceMismatch,
+    #[error("Required authentication methods missing")]
+    RequiredAmrMissing,
+    #[error("Required authentication methods mismatch")]
+    RequiredAmrMismatch,
+    #[error("Required authentication context class missing")]
+    RequiredAcrMissing,
+    #[error("Required authentication context class mismatch")]
+    RequiredAcrMismatch,
     #[error("Required issuer mismatch")]
     RequiredIssuerMismatch,
     #[error("Required issuer missing")]
@@ -44,14 +84,108 @@ pub enum JWTError {
     RequiredAudienceMissing,
     #[error("Required audience mismatch")]
     RequiredAudienceMismatch,
+    #[error("Audience not allowed")]
+    AudienceNotAllowed,
+    #[error("Required claim missing: [{0}]")]
+    RequiredClaimMissing(&'static str),
+    #[error("Access token hash missing")]
+    AccessTokenHashMissing,
+    #[error("Access token hash mismatch")]
+    AccessTokenHashMismatch,
+    #[error("Authorization code hash missing")]
+    CodeHashMissing,
+    #[error("Authorization code hash mismatch")]
+    CodeHashMismatch,
+    #[error("DPoP HTTP method missing")]
+    HttpMethodMissing,
+    #[error("DPoP HTTP method mismatch")]
+    HttpMethodMismatch,
+    #[error("DPoP HTTP URI missing")]
+    HttpUriMissing,
+    #[error("DPoP HTTP URI mismatch")]
+    HttpUriMismatch,
+    #[error("DPoP access token hash missing")]
+    DPoPAccessTokenHashMissing,
+    #[error("DPoP access token hash mismatch")]
+    DPoPAccessTokenHashMismatch,
+    #[error("Unsupported JWT algorithm")]
+    UnsupportedAlgorithm,
+    #[error("Unsupported elliptic curve")]
+    UnsupportedCurve,
+    #[error("Custom claim collides with a standard claim: [{0}]")]
+    ReservedClaimCollision(String),
     #[error("Unsupported RSA modulus")]
     UnsupportedRSAModulus,
+    #[error("Key is shorter than required by the algorithm")]
+    KeyTooShort,
     #[error("Invalid public key")]
     InvalidPublicKey,
     #[error("Invalid key pair")]
     InvalidKeyPair,
     #[error("At most one audience can be represented as a string instead of a set")]
     TooManyAudiences,
+    #[error("RFC 3339 timestamps require the chrono feature")]
+    RFC3339TimestampsUnsupported,
+}
+
+/// Reason why a key couldn't be imported
+///
+/// Key import functions fail with `JWTError::InvalidPublicKey` or `JWTError::InvalidKeyPair`,
+/// and attach the reason as a context, that can be retrieved with
+/// `error.downcast_ref::<KeyImportErrorReason>()`.
+///
+/// Like `JWTError`, this enumeration may get new variants in minor releases.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum KeyImportErrorReason {
+    #[error("Key is for a different algorithm")]
+    WrongAlgorithm,
+    #[error("Key is encrypted")]
+    Encrypted,
+    #[error("Wrong passphrase for the encrypted key")]
+    WrongPassphrase,
+    #[error("Unsupported key encryption scheme")]
+    UnsupportedEncryption,
+    #[error("Key is truncated")]
+    Truncated,
+    #[error("Key is for an unsupported curve")]
+    UnsupportedCurve,
+    #[error("Key is not properly base64-encoded")]
+    BadBase64,
+    #[error("Key is invalid")]
+    Invalid,
+}
+
+impl KeyImportErrorReason {
+    pub(crate) fn public_key_error(self) -> Error {
+        Error::new(JWTError::InvalidPublicKey).context(self)
+    }
+
+    pub(crate) fn key_pair_error(self) -> Error {
+        Error::new(JWTError::InvalidKeyPair).context(self)
+    }
+}
+
+/// Reason why verification options are inconsistent
+///
+/// Returned by `VerificationOptions::validate_config()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    #[error("max_validity is zero, so almost all tokens would be rejected")]
+    ZeroMaxValidity,
+    #[error("max_auth_age is zero, so almost all tokens would be rejected")]
+    ZeroMaxAuthAge,
+    #[error("expired_grace has no effect unless accept_expired is set")]
+    ExpiredGraceWithoutAcceptExpired,
+    #[error("audience_delimiters have no effect unless audiences are required or allowed")]
+    AudienceDelimitersWithoutRequiredAudience,
+    #[error("A required audience is not in allowed_audiences, so all tokens would be rejected")]
+    RequiredAudienceNotAllowed,
+    #[error("The required subject is not in allowed_subjects, so all tokens would be rejected")]
+    RequiredSubjectNotAllowed,
+    #[error("reject_before is in the future, so all tokens would be rejected")]
+    RejectBeforeInFuture,
 }
 
 impl From<&str> for JWTError {
@@ -1,39 +1,69 @@
 use serde::{Deserialize, Serialize};
 
+/// The header of a token
+///
+/// A custom header can be given to `sign_with_header()`, for example to set both a footer and
+/// a content type. The algorithm is always set by the signing key.
+///
+/// Members are serialized in the order they are declared: `alg`, `kid`, `typ`, and then
+/// the other members in lexicographic order, so that identical headers always have the same encoding.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct JWTHeader {
+pub struct JWTHeader {
+    /// The JWT algorithm
     #[serde(rename = "alg")]
     pub algorithm: String,
 
+    /// The key, or public key identifier
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+
+    /// The signature type, `JWT` by default
+    #[serde(rename = "typ", default, skip_serializing_if = "Option::is_none")]
+    pub signature_type: Option<String>,
+
+    /// Whether the payload is base64url-encoded (RFC 7797)
+    #[serde(rename = "b64", default, skip_serializing_if = "Option::is_none")]
+    pub base64_encoded_payload: Option<bool>,
+
+    /// The set of critical properties
+    #[serde(rename = "crit", default, skip_serializing_if = "Option::is_none")]
+    pub critical: Option<Vec<String>>,
+
+    /// The content type
     #[serde(rename = "cty", default, skip_serializing_if = "Option::is_none")]
     pub content_type: Option<String>,
 
+    /// The content encryption algorithm, only present in encrypted tokens
+    #[serde(rename = "enc", default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<String>,
+
+    /// An application-defined footer
+    #[serde(rename = "footer", default, skip_serializing_if = "Option::is_none")]
+    pub footer: Option<String>,
+
+    /// The key set URL
     #[serde(rename = "jku", default, skip_serializing_if = "Option::is_none")]
     pub key_set_url: Option<String>,
 
+    /// The public key
     #[serde(rename = "jwk", default, skip_serializing_if = "Option::is_none")]
     pub public_key: Option<String>,
 
-    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
-    pub key_id: Option<String>,
-
-    #[serde(rename = "x5u", default, skip_serializing_if = "Option::is_none")]
-    pub certificate_url: Option<String>,
-
+    /// The certificate chain
     #[serde(rename = "x5c", default, skip_serializing_if = "Option::is_none")]
     pub certificate_chain: Option<Vec<String>>,
 
+    /// The SHA-1 certificate fingerprint
     #[serde(rename = "x5t", default, skip_serializing_if = "Option::is_none")]
     pub certificate_sha1_thumbprint: Option<String>,
 
+    /// The SHA-256 certificate fingerprint
     #[serde(rename = "x5t#S256", default, skip_serializing_if = "Option::is_none")]
     pub certificate_sha256_thumbprint: Option<String>,
 
-    #[serde(rename = "typ", default, skip_serializing_if = "Option::is_none")]
-    pub signature_type: Option<String>,
-
-    #[serde(rename = "crit", default, skip_serializing_if = "Option::is_none")]
-    pub critical: Option<Vec<String>>,
+    /// The certificate URL
+    #[serde(rename = "x5u", default, skip_serializing_if = "Option::is_none")]
+    pub certificate_url: Option<String>,
 }
 
 impl Default for JWTHeader {
@@ -41,6 +71,7 @@ impl Default for JWTHeader {
         JWTHeader {
             algorithm: "Not set".to_string(),
             content_type: None,
+            encryption: None,
             key_set_url: None,
             public_key: None,
             key_id: None,
@@ -49,7 +80,9 @@ impl Default for JWTHeader {
             certificate_sha1_thumbprint: None,
             certificate_sha256_thumbprint: None,
             signature_type: Some("JWT".to_string()),
+            base64_encoded_payload: None,
             critical: None,
+            footer: None,
         }
     }
 }
@@ -0,0 +1,447 @@
+use aes::cipher::{BlockCipher, NewBlockCipher};
+use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
+use hmac::Hmac;
+use sha2::Sha256;
+use simple_asn1::ASN1Block;
+use zeroize::Zeroize;
+
+use crate::common::*;
+use crate::error::*;
+
+// DER-encoded object identifiers, without the tag and length
+const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+const OID_HMAC_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+const OID_AES128_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x02];
+const OID_AES192_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x16];
+const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+pub(crate) const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+pub(crate) const OID_SECP256K1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x0a];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_NULL: u8 = 0x05;
+const DER_OID: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+
+/// Upper bound on the number of PBKDF2 iterations, to avoid spending unbounded time on a key
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+fn is_oid(block: &ASN1Block, expected: &[u8]) -> bool {
+    match block {
+        ASN1Block::ObjectIdentifier(_, oid) => oid.as_raw().ok().as_deref() == Some(expected),
+        _ => false,
+    }
+}
+
+fn parse_der(der: &[u8]) -> Result<Vec<ASN1Block>, KeyImportErrorReason> {
+    simple_asn1::from_der(der).map_err(|_| KeyImportErrorReason::Invalid)
+}
+
+/// Encode a DER element, whose contents are the concatenation of `contents`
+///
+/// The capacity is exact, so that secret contents are never left behind by a reallocation.
+fn der_element(tag: u8, contents: &[&[u8]]) -> Vec<u8> {
+    let len: usize = contents.iter().map(|x| x.len()).sum();
+    let len_be = len.to_be_bytes();
+    let len_be = &len_be[len_be.iter().take_while(|&&x| x == 0).count()..];
+    let mut der = Vec::with_capacity(2 + len_be.len() + len);
+    der.push(tag);
+    if len < 0x80 {
+        der.push(len as u8);
+    } else {
+        der.push(0x80 | len_be.len() as u8);
+        der.extend_from_slice(len_be);
+    }
+    contents.iter().for_each(|x| der.extend_from_slice(x));
+    der
+}
+
+/// Encode a DER sequence of already encoded elements
+pub(crate) fn der_sequence(elements: &[&[u8]]) -> Vec<u8> {
+    der_element(DER_SEQUENCE, elements)
+}
+
+/// Encode a non-negative, big-endian integer as a DER integer
+pub(crate) fn der_unsigned_integer(be: &[u8]) -> Vec<u8> {
+    let be = &be[be.iter().take_while(|&&x| x == 0).count()..];
+    match be.first() {
+        None => der_element(DER_INTEGER, &[&[0]]),
+        Some(&x) if x & 0x80 != 0 => der_element(DER_INTEGER, &[&[0], be]),
+        Some(_) => der_element(DER_INTEGER, &[be]),
+    }
+}
+
+fn private_key_info(algorithm: &[u8], private_key: &[u8]) -> Vec<u8> {
+    let mut private_key = der_element(DER_OCTET_STRING, &[private_key]);
+    let der = der_sequence(&[&der_unsigned_integer(&[0]), algorithm, &private_key]);
+    private_key.zeroize();
+    der
+}
+
+fn subject_public_key_info(algorithm: &[u8], public_key: &[u8]) -> Vec<u8> {
+    der_sequence(&[algorithm, &der_element(DER_BIT_STRING, &[&[0], public_key])])
+}
+
+fn ec_algorithm(curve_oid: &[u8]) -> Vec<u8> {
+    der_sequence(&[
+        &der_element(DER_OID, &[OID_EC_PUBLIC_KEY]),
+        &der_element(DER_OID, &[curve_oid]),
+    ])
+}
+
+fn ed25519_algorithm() -> Vec<u8> {
+    der_sequence(&[&der_element(DER_OID, &[OID_ED25519])])
+}
+
+fn rsa_algorithm() -> Vec<u8> {
+    der_sequence(&[
+        &der_element(DER_OID, &[OID_RSA_ENCRYPTION]),
+        &der_element(DER_NULL, &[]),
+    ])
+}
+
+/// Encode an elliptic curve secret scalar as a PKCS#8 private key
+pub(crate) fn ec_private_key_der(secret_key: &[u8], curve_oid: &[u8]) -> Vec<u8> {
+    let mut ec_private_key = der_sequence(&[
+        &der_unsigned_integer(&[1]),
+        &der_element(DER_OCTET_STRING, &[secret_key]),
+    ]);
+    let der = private_key_info(&ec_algorithm(curve_oid), &ec_private_key);
+    ec_private_key.zeroize();
+    der
+}
+
+/// Encode a SEC1 elliptic curve point as a `SubjectPublicKeyInfo` structure
+pub(crate) fn ec_public_key_der(point: &[u8], curve_oid: &[u8]) -> Vec<u8> {
+    subject_public_key_info(&ec_algorithm(curve_oid), point)
+}
+
+/// Encode an Ed25519 seed as a PKCS#8 private key, as specified in RFC 8410
+pub(crate) fn ed25519_private_key_der(seed: &[u8]) -> Vec<u8> {
+    let mut curve_private_key = der_element(DER_OCTET_STRING, &[seed]);
+    let der = private_key_info(&ed25519_algorithm(), &curve_private_key);
+    curve_private_key.zeroize();
+    der
+}
+
+/// Encode an Ed25519 public key as a `SubjectPublicKeyInfo` structure
+pub(crate) fn ed25519_public_key_der(public_key: &[u8]) -> Vec<u8> {
+    subject_public_key_info(&ed25519_algorithm(), public_key)
+}
+
+/// Wrap a PKCS#1 `RSAPrivateKey` structure into a PKCS#8 private key
+pub(crate) fn rsa_private_key_der(pkcs1: &[u8]) -> Vec<u8> {
+    private_key_info(&rsa_algorithm(), pkcs1)
+}
+
+/// Wrap a PKCS#1 `RSAPublicKey` structure into a `SubjectPublicKeyInfo` structure
+pub(crate) fn rsa_public_key_der(pkcs1: &[u8]) -> Vec<u8> {
+    subject_public_key_info(&rsa_algorithm(), pkcs1)
+}
+
+// `usize::is_multiple_of()` requires a more recent compiler than this crate supports
+#[allow(clippy::manual_is_multiple_of)]
+fn aes_cbc_decrypt<C: BlockCipher + NewBlockCipher>(
+    key: &[u8],
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, KeyImportErrorReason> {
+    if ciphertext.is_empty() || ciphertext.len() % 16 != 0 {
+        return Err(KeyImportErrorReason::Invalid);
+    }
+    let cipher = Cbc::<C, Pkcs7>::new_var(key, iv).map_err(|_| KeyImportErrorReason::Invalid)?;
+    // Blocks are decrypted in place, so that no copy of the plaintext is left behind
+    let mut plaintext = ciphertext.to_vec();
+    let plaintext_len = match cipher.decrypt(&mut plaintext) {
+        Ok(unpadded) => unpadded.len(),
+        // A wrong key leaves random bytes where the PKCS#7 padding is expected
+        Err(_) => {
+            plaintext.zeroize();
+            return Err(KeyImportErrorReason::WrongPassphrase);
+        }
+    };
+    plaintext.truncate(plaintext_len);
+    Ok(plaintext)
+}
+
+/// Zeroize the byte strings of parsed DER blocks, that may hold copies of private key material
+fn zeroize_blocks(blocks: &mut [ASN1Block]) {
+    for block in blocks {
+        match block {
+            ASN1Block::OctetString(_, x) | ASN1Block::BitString(_, _, x) => x.zeroize(),
+            ASN1Block::Sequence(_, x) | ASN1Block::Set(_, x) => zeroize_blocks(x),
+            ASN1Block::Explicit(_, _, _, x) => zeroize_blocks(std::slice::from_mut(x)),
+            _ => {}
+        }
+    }
+}
+
+/// Decrypt a PKCS#8 `EncryptedPrivateKeyInfo` structure, and return the DER-encoded `PrivateKeyInfo`
+///
+/// Only PBES2, with PBKDF2-HMAC-SHA256 and AES-CBC is supported.
+fn decrypt_der(der: &[u8], passphrase: &[u8]) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let unsupported = KeyImportErrorReason::UnsupportedEncryption;
+    let invalid = KeyImportErrorReason::Invalid;
+    let blocks = parse_der(der)?;
+    let (algorithm, ciphertext) = match blocks.as_slice() {
+        [ASN1Block::Sequence(_, x)] => match x.as_slice() {
+            [ASN1Block::Sequence(_, algorithm), ASN1Block::OctetString(_, ciphertext)] => {
+                (algorithm, ciphertext)
+            }
+            _ => return Err(invalid),
+        },
+        _ => return Err(invalid),
+    };
+    let (kdf, encryption_scheme) = match algorithm.as_slice() {
+        [oid, ASN1Block::Sequence(_, params)] if is_oid(oid, OID_PBES2) => {
+            match params.as_slice() {
+                [ASN1Block::Sequence(_, kdf), ASN1Block::Sequence(_, encryption_scheme)] => {
+                    (kdf, encryption_scheme)
+                }
+                _ => return Err(invalid),
+            }
+        }
+        _ => return Err(unsupported),
+    };
+    let kdf_params = match kdf.as_slice() {
+        [oid, ASN1Block::Sequence(_, kdf_params)] if is_oid(oid, OID_PBKDF2) => kdf_params,
+        _ => return Err(unsupported),
+    };
+    let (salt, iterations, prf) = match kdf_params.as_slice() {
+        [ASN1Block::OctetString(_, salt), ASN1Block::Integer(_, iterations), rest @ ..] => {
+            (salt, iterations, rest.last())
+        }
+        _ => return Err(invalid),
+    };
+    // The default PRF, HMAC-SHA1, is not supported
+    match prf {
+        Some(ASN1Block::Sequence(_, prf))
+            if prf.first().map(|x| is_oid(x, OID_HMAC_WITH_SHA256)) == Some(true) => {}
+        _ => return Err(unsupported),
+    }
+    let iterations = match iterations.to_bytes_be() {
+        (_, bytes) if bytes.len() <= 4 => bytes.iter().fold(0u32, |n, &x| (n << 8) | x as u32),
+        _ => return Err(invalid),
+    };
+    if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(invalid);
+    }
+    let (cipher, iv) = match encryption_scheme.as_slice() {
+        [cipher, ASN1Block::OctetString(_, iv)] => (cipher, iv),
+        _ => return Err(unsupported),
+    };
+    let key_len = if is_oid(cipher, OID_AES128_CBC) {
+        16
+    } else if is_oid(cipher, OID_AES192_CBC) {
+        24
+    } else if is_oid(cipher, OID_AES256_CBC) {
+        32
+    } else {
+        return Err(unsupported);
+    };
+    let mut key = vec![0u8; key_len];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase, salt, iterations, &mut key);
+    let plaintext = match key_len {
+        16 => aes_cbc_decrypt::<aes::Aes128>(&key, iv, ciphertext),
+        24 => aes_cbc_decrypt::<aes::Aes192>(&key, iv, ciphertext),
+        _ => aes_cbc_decrypt::<aes::Aes256>(&key, iv, ciphertext),
+    };
+    key.zeroize();
+    let mut plaintext = plaintext?;
+    // The padding can be valid by chance, so also check that the result is a DER sequence
+    match simple_asn1::from_der(&plaintext) {
+        Ok(mut blocks) => zeroize_blocks(&mut blocks),
+        Err(_) => {
+            plaintext.zeroize();
+            return Err(KeyImportErrorReason::WrongPassphrase);
+        }
+    }
+    Ok(plaintext)
+}
+
+/// Parse a PEM document, rejecting encrypted keys
+pub(crate) fn parse_pem(pem: &str) -> Result<rsa::pem::Pem, KeyImportErrorReason> {
+    if pem.lines().any(|line| {
+        (line.starts_with("-----BEGIN ") || line.starts_with("Proc-Type:"))
+            && line.contains("ENCRYPTED")
+    }) {
+        return Err(KeyImportErrorReason::Encrypted);
+    }
+    rsa::pem::parse(normalize_pem(pem)).map_err(|e| match e {
+        rsa::pem::PemError::InvalidData(_) => KeyImportErrorReason::BadBase64,
+        _ => KeyImportErrorReason::Truncated,
+    })
+}
+
+/// Parse a PEM document with the given label, and return its DER-encoded contents
+pub(crate) fn pem_contents(pem: &str, label: &str) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let mut parsed_pem = parse_pem(pem)?;
+    if parsed_pem.tag != label {
+        parsed_pem.contents.zeroize();
+        return Err(KeyImportErrorReason::WrongAlgorithm);
+    }
+    Ok(parsed_pem.contents)
+}
+
+/// Encode a DER document as PEM, with the given label
+pub(crate) fn to_pem(label: &str, der: Vec<u8>) -> String {
+    let mut pem = rsa::pem::Pem {
+        tag: label.to_string(),
+        contents: der,
+    };
+    let encoded = rsa::pem::encode_config(
+        &pem,
+        rsa::pem::EncodeConfig {
+            line_ending: rsa::pem::LineEnding::LF,
+        },
+    );
+    pem.contents.zeroize();
+    encoded
+}
+
+/// Decrypt an `ENCRYPTED PRIVATE KEY` PEM document, and return the DER-encoded PKCS#8 private key
+pub(crate) fn decrypt_pem(pem: &str, passphrase: &[u8]) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let mut parsed_pem = rsa::pem::parse(normalize_pem(pem)).map_err(|e| match e {
+        rsa::pem::PemError::InvalidData(_) => KeyImportErrorReason::BadBase64,
+        _ => KeyImportErrorReason::Truncated,
+    })?;
+    if parsed_pem.tag != "ENCRYPTED PRIVATE KEY" {
+        return Err(KeyImportErrorReason::Invalid);
+    }
+    let der = decrypt_der(&parsed_pem.contents, passphrase);
+    parsed_pem.contents.zeroize();
+    der
+}
+
+/// Extract the raw secret scalar out of a PKCS#8-encoded elliptic curve private key
+pub(crate) fn ec_secret_key(der: &[u8], curve_oid: &[u8]) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let mut blocks = parse_der(der)?;
+    let res = ec_secret_key_from_blocks(&blocks, curve_oid);
+    zeroize_blocks(&mut blocks);
+    res
+}
+
+fn ec_secret_key_from_blocks(
+    blocks: &[ASN1Block],
+    curve_oid: &[u8],
+) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let invalid = KeyImportErrorReason::Invalid;
+    let (algorithm, private_key) = match blocks {
+        [ASN1Block::Sequence(_, x)] => match x.as_slice() {
+            [ASN1Block::Integer(_, _), ASN1Block::Sequence(_, algorithm), ASN1Block::OctetString(_, private_key), ..] => {
+                (algorithm, private_key)
+            }
+            _ => return Err(invalid),
+        },
+        _ => return Err(invalid),
+    };
+    match algorithm.as_slice() {
+        [oid, curve] if is_oid(oid, OID_EC_PUBLIC_KEY) => {
+            if !is_oid(curve, curve_oid) {
+                return Err(KeyImportErrorReason::UnsupportedCurve);
+            }
+        }
+        _ => return Err(KeyImportErrorReason::WrongAlgorithm),
+    }
+    let mut private_key_blocks = parse_der(private_key)?;
+    let res = match private_key_blocks.as_slice() {
+        [ASN1Block::Sequence(_, x)] => match x.as_slice() {
+            [ASN1Block::Integer(_, _), ASN1Block::OctetString(_, secret_key), ..] => {
+                Ok(secret_key.clone())
+            }
+            _ => Err(invalid),
+        },
+        _ => Err(invalid),
+    };
+    zeroize_blocks(&mut private_key_blocks);
+    res
+}
+
+/// Extract the raw seed out of a PKCS#8-encoded Ed25519 private key
+pub(crate) fn ed25519_seed(der: &[u8]) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let mut blocks = parse_der(der)?;
+    let res = ed25519_seed_from_blocks(&blocks);
+    zeroize_blocks(&mut blocks);
+    res
+}
+
+fn ed25519_seed_from_blocks(blocks: &[ASN1Block]) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let invalid = KeyImportErrorReason::Invalid;
+    let (algorithm, private_key) = match blocks {
+        [ASN1Block::Sequence(_, x)] => match x.as_slice() {
+            [ASN1Block::Integer(_, _), ASN1Block::Sequence(_, algorithm), ASN1Block::OctetString(_, private_key), ..] => {
+                (algorithm, private_key)
+            }
+            _ => return Err(invalid),
+        },
+        _ => return Err(invalid),
+    };
+    match algorithm.as_slice() {
+        [oid] if is_oid(oid, OID_ED25519) => {}
+        _ => return Err(KeyImportErrorReason::WrongAlgorithm),
+    }
+    let mut private_key_blocks = parse_der(private_key)?;
+    let res = match private_key_blocks.as_slice() {
+        [ASN1Block::OctetString(_, seed)] => Ok(seed.clone()),
+        _ => Err(invalid),
+    };
+    zeroize_blocks(&mut private_key_blocks);
+    res
+}
+
+/// Extract the raw public key out of a `SubjectPublicKeyInfo` structure
+///
+/// For elliptic curve keys, `curve_oid` is the expected curve, and the result is a SEC1 point.
+pub(crate) fn spki_public_key(
+    der: &[u8],
+    curve_oid: Option<&[u8]>,
+) -> Result<Vec<u8>, KeyImportErrorReason> {
+    let invalid = KeyImportErrorReason::Invalid;
+    let blocks = parse_der(der)?;
+    let (algorithm, public_key) = match blocks.as_slice() {
+        [ASN1Block::Sequence(_, x)] => match x.as_slice() {
+            [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, bits, public_key)]
+                if *bits == public_key.len() * 8 =>
+            {
+                (algorithm, public_key)
+            }
+            _ => return Err(invalid),
+        },
+        _ => return Err(invalid),
+    };
+    match (algorithm.as_slice(), curve_oid) {
+        ([oid], None) if is_oid(oid, OID_ED25519) => {}
+        ([oid, curve], Some(curve_oid)) if is_oid(oid, OID_EC_PUBLIC_KEY) => {
+            if !is_oid(curve, curve_oid) {
+                return Err(KeyImportErrorReason::UnsupportedCurve);
+            }
+        }
+        _ => return Err(KeyImportErrorReason::WrongAlgorithm),
+    }
+    Ok(public_key.clone())
+}
+
+/// Check that a PKCS#8 private key or a `SubjectPublicKeyInfo` structure is for an RSA key
+pub(crate) fn check_rsa_algorithm(der: &[u8]) -> Result<(), KeyImportErrorReason> {
+    let mut blocks = parse_der(der)?;
+    let res = match blocks.as_slice() {
+        [ASN1Block::Sequence(_, x)] => match x.as_slice() {
+            [ASN1Block::Integer(_, _), ASN1Block::Sequence(_, algorithm), ASN1Block::OctetString(_, _), ..]
+            | [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, _)] => {
+                match algorithm.as_slice() {
+                    [oid, ..] if is_oid(oid, OID_RSA_ENCRYPTION) => Ok(()),
+                    _ => Err(KeyImportErrorReason::WrongAlgorithm),
+                }
+            }
+            _ => Err(KeyImportErrorReason::Invalid),
+        },
+        _ => Err(KeyImportErrorReason::Invalid),
+    };
+    zeroize_blocks(&mut blocks);
+    res
+}
@@ -1,3 +1,56 @@
+use coarsetime::UnixTimeStamp;
+
+/// Truncate a timestamp to a whole number of seconds, rounding toward the past.
+pub(crate) fn floor_to_secs(time: UnixTimeStamp) -> UnixTimeStamp {
+    UnixTimeStamp::from_secs(time.as_secs())
+}
+
+/// Names of the claims deserialized as timestamps
+const TIME_CLAIM_NAMES: &[&str] = &["iat", "exp", "nbf", "auth_time"];
+
+/// Non-standard representation of time claims as RFC 3339 strings
+///
+/// This is not compliant with RFC 7519, and is only meant for tokens exchanged between cooperating services.
+#[cfg(feature = "chrono")]
+pub(crate) mod rfc3339 {
+    use anyhow::{anyhow, Error};
+    use coarsetime::UnixTimeStamp;
+
+    use super::TIME_CLAIM_NAMES;
+    use crate::common::{unix_timestamp_to_chrono, ToUnixTimeStamp};
+
+    /// Replace numeric time claims with RFC 3339 strings
+    pub fn encode(claims: &mut serde_json::Value) {
+        for name in TIME_CLAIM_NAMES {
+            if let Some(value) = claims.get_mut(*name) {
+                if let Some(secs) = value.as_u64() {
+                    let date = unix_timestamp_to_chrono(UnixTimeStamp::from_secs(secs));
+                    *value = date
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                        .into();
+                }
+            }
+        }
+    }
+
+    /// Replace RFC 3339 time claims with numbers
+    pub fn decode(claims: &mut serde_json::Value) -> Result<(), Error> {
+        for name in TIME_CLAIM_NAMES {
+            if let Some(value) = claims.get_mut(*name) {
+                if let Some(date) = value.as_str() {
+                    let date = chrono::DateTime::parse_from_rfc3339(date)
+                        .map_err(|_| anyhow!("Invalid RFC 3339 date for `{}`", name))?
+                        .with_timezone(&chrono::Utc);
+                    *value = date.to_unix_timestamp().as_secs().into();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Time claims are unsigned: negative values, as well as values larger than `MAX_SECS`, are rejected
+/// rather than clamped. Token verification reports them as `JWTError::InvalidTimeClaim`.
 pub mod unix_timestamp {
     use serde::{
         de::{Error as DeError, Visitor},
@@ -7,6 +60,11 @@ pub mod unix_timestamp {
     use coarsetime::UnixTimeStamp;
     use std::fmt;
 
+    /// Largest number of seconds a `UnixTimeStamp` can represent (February 2106)
+    ///
+    /// Timestamps are stored as 32.32 fixed-point numbers, so larger values would be silently truncated.
+    pub const MAX_SECS: u64 = u32::MAX as u64;
+
     struct TimestampVisitor;
 
     impl<'de> Visitor<'de> for TimestampVisitor {
@@ -16,13 +74,19 @@ pub mod unix_timestamp {
         where
             E: DeError,
         {
-            Ok(UnixTimeStamp::from_secs(value as _))
+            if value < 0 {
+                return Err(E::custom(format!("Unix timestamp out of range: {}", value)));
+            }
+            self.visit_u64(value as u64)
         }
 
         fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
         where
             E: DeError,
         {
+            if value > MAX_SECS {
+                return Err(E::custom(format!("Unix timestamp out of range: {}", value)));
+            }
             Ok(UnixTimeStamp::from_secs(value))
         }
 
@@ -31,11 +95,34 @@ pub mod unix_timestamp {
         }
     }
 
+    /// Return `true` if a time claim of `claims_json` is a number that doesn't fit in a `UnixTimeStamp`
+    ///
+    /// This includes negative and non-integer values. It is only used to report a meaningful error
+    /// after deserialization has failed.
+    pub(crate) fn has_invalid_time_claim(claims_json: &[u8]) -> bool {
+        let claims: serde_json::Map<String, serde_json::Value> =
+            match serde_json::from_slice(claims_json) {
+                Ok(claims) => claims,
+                Err(_) => return false,
+            };
+        super::TIME_CLAIM_NAMES
+            .iter()
+            .any(|name| match claims.get(*name) {
+                Some(serde_json::Value::Number(value)) => {
+                    !matches!(value.as_u64(), Some(value) if value <= MAX_SECS)
+                }
+                _ => false,
+            })
+    }
+
+    /// Timestamps are always serialized as whole seconds, truncated toward the past.
+    ///
+    /// For `exp`, this means that a token can never be valid longer than requested.
     pub fn serialize<S: Serializer>(
         time: &Option<UnixTimeStamp>,
         serializer: S,
     ) -> Result<S::Ok, S::Error> {
-        serializer.serialize_u64(time.unwrap().as_secs())
+        serializer.serialize_u64(super::floor_to_secs(time.unwrap()).as_secs())
     }
 
     pub fn deserialize<'de, D: Deserializer<'de>>(
@@ -45,6 +132,11 @@ pub mod unix_timestamp {
     }
 }
 
+/// Durations are represented as a number of seconds, like timestamps
+pub mod duration {
+    pub use super::unix_timestamp::{deserialize, serialize};
+}
+
 pub mod audiences {
     use super::super::claims::Audiences;
     use serde::{
@@ -59,24 +151,32 @@ pub mod audiences {
     struct AudiencesVisitor;
 
     impl<'de> Visitor<'de> for AudiencesVisitor {
-        type Value = Audiences;
+        type Value = Option<Audiences>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
             formatter.write_str("Audiences")
         }
 
+        // `null` is treated like a missing claim
+        fn visit_unit<E>(self) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(None)
+        }
+
         fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
         where
             E: DeError,
         {
-            Ok(Audiences::AsString(value))
+            Ok(Some(Audiences::AsString(value)))
         }
 
         fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
         where
             E: DeError,
         {
-            Ok(Audiences::AsString(value.to_string()))
+            Ok(Some(Audiences::AsString(value.to_string())))
         }
 
         fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
@@ -88,7 +188,7 @@ pub mod audiences {
             while let Some(audience) = seq.next_element()? {
                 audiences_set.insert(audience);
             }
-            Ok(Audiences::AsSet(audiences_set))
+            Ok(Some(Audiences::AsSet(audiences_set)))
         }
     }
 
@@ -106,7 +206,167 @@ pub mod audiences {
     pub fn deserialize<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Option<Audiences>, D::Error> {
-        let audiences = deserializer.deserialize_any(AudiencesVisitor)?;
-        Ok(Some(audiences))
+        deserializer.deserialize_any(AudiencesVisitor)
+    }
+}
+
+pub mod keys {
+    use serde::{
+        de::{IgnoredAny, MapAccess, Visitor},
+        Deserializer,
+    };
+    use std::fmt;
+
+    struct KeysVisitor;
+
+    impl<'de> Visitor<'de> for KeysVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("Object")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut keys = Vec::with_capacity(map.size_hint().unwrap_or(0));
+            while let Some(key) = map.next_key()? {
+                map.next_value::<IgnoredAny>()?;
+                keys.push(key);
+            }
+            Ok(keys)
+        }
+    }
+
+    /// Return the top-level keys of an object, without deserializing the values
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        deserializer.deserialize_map(KeysVisitor)
+    }
+}
+
+pub mod borrowed {
+    use super::super::claims::BorrowedAudiences;
+    use serde::{
+        de::{Error as DeError, SeqAccess, Visitor},
+        Deserialize, Deserializer,
+    };
+    use std::borrow::Cow;
+    use std::fmt;
+
+    /// A string borrowed from the input whenever it doesn't contain escape sequences
+    struct CowStr<'a>(Cow<'a, str>);
+
+    struct CowStrVisitor;
+
+    impl<'de> Visitor<'de> for CowStrVisitor {
+        type Value = CowStr<'de>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("String")
+        }
+
+        fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(CowStr(Cow::Borrowed(value)))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(CowStr(Cow::Owned(value.to_string())))
+        }
+
+        fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(CowStr(Cow::Owned(value)))
+        }
+    }
+
+    impl<'de: 'a, 'a> Deserialize<'de> for CowStr<'a> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_str(CowStrVisitor)
+        }
+    }
+
+    pub fn deserialize_str<'de: 'a, 'a, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Cow<'a, str>>, D::Error> {
+        let value: Option<CowStr<'a>> = Deserialize::deserialize(deserializer)?;
+        Ok(value.map(|x| x.0))
+    }
+
+    pub fn deserialize_str_seq<'de: 'a, 'a, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<Cow<'a, str>>>, D::Error> {
+        let values: Option<Vec<CowStr<'a>>> = Deserialize::deserialize(deserializer)?;
+        Ok(values.map(|values| values.into_iter().map(|x| x.0).collect()))
+    }
+
+    struct AudiencesVisitor;
+
+    impl<'de> Visitor<'de> for AudiencesVisitor {
+        type Value = Option<BorrowedAudiences<'de>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("Audiences")
+        }
+
+        // `null` is treated like a missing claim
+        fn visit_unit<E>(self) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(None)
+        }
+
+        fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(Some(BorrowedAudiences::AsString(Cow::Borrowed(value))))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(Some(BorrowedAudiences::AsString(Cow::Owned(
+                value.to_string(),
+            ))))
+        }
+
+        fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(Some(BorrowedAudiences::AsString(Cow::Owned(value))))
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut audiences = Vec::with_capacity(seq.size_hint().unwrap_or(1));
+            while let Some(CowStr(audience)) = seq.next_element()? {
+                if !audiences.contains(&audience) {
+                    audiences.push(audience);
+                }
+            }
+            Ok(Some(BorrowedAudiences::AsSet(audiences)))
+        }
+    }
+
+    pub fn deserialize_audiences<'de: 'a, 'a, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<BorrowedAudiences<'a>>, D::Error> {
+        deserializer.deserialize_any(AudiencesVisitor)
     }
 }
@@ -1,10 +1,11 @@
 use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::claims::*;
 use crate::common::*;
 use crate::error::*;
 use crate::jwt_header::*;
+use crate::serde_additions;
 
 pub const MAX_HEADER_LENGTH: usize = 8192;
 
@@ -14,6 +15,10 @@ pub struct Token;
 /// JWT token information useful before signature/tag verification
 pub struct TokenMetadata {
     jwt_header: JWTHeader,
+    issuer: Option<String>,
+    token_len: usize,
+    claim_count: Option<usize>,
+    verified_algorithm: Option<&'static str>,
 }
 
 impl TokenMetadata {
@@ -71,24 +76,65 @@ impl TokenMetadata {
     pub fn critical(&self) -> Option<&[String]> {
         self.jwt_header.critical.as_deref()
     }
+
+    /// The application-defined footer for this token
+    pub fn footer(&self) -> Option<&str> {
+        self.jwt_header.footer.as_deref()
+    }
+
+    /// The issuer of this token, as claimed by the yet unverified claims
+    pub fn issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    /// The length of the encoded token, in bytes
+    pub fn token_len(&self) -> usize {
+        self.token_len
+    }
+
+    /// The number of top-level members of the claims
+    ///
+    /// This is only set in metadata returned along with verified claims. It is `None` for
+    /// metadata of unverified tokens, whose claims are not entirely decoded.
+    pub fn claim_count(&self) -> Option<usize> {
+        self.claim_count
+    }
+
+    /// The algorithm the token was verified with
+    ///
+    /// This is only set in metadata returned along with verified claims, where it always matches
+    /// the algorithm of the verification key. It is `None` for metadata of unverified tokens.
+    pub fn verified_algorithm(&self) -> Option<&'static str> {
+        self.verified_algorithm
+    }
 }
 
 impl Token {
     pub(crate) fn build<AuthenticationOrSignatureFn, CustomClaims: Serialize + DeserializeOwned>(
         jwt_header: &JWTHeader,
-        claims: JWTClaims<CustomClaims>,
+        claims: &JWTClaims<CustomClaims>,
         authentication_or_signature_fn: AuthenticationOrSignatureFn,
     ) -> Result<String, Error>
     where
         AuthenticationOrSignatureFn: FnOnce(&str) -> Result<Vec<u8>, Error>,
     {
         let jwt_header_json = serde_json::to_string(&jwt_header)?;
-        let claims_json = serde_json::to_string(&claims)?;
-        let authenticated = format!(
-            "{}.{}",
-            Base64UrlSafeNoPadding::encode_to_string(jwt_header_json)?,
-            Base64UrlSafeNoPadding::encode_to_string(claims_json)?
-        );
+        let claims_json = claims.to_json()?;
+        // Encode both segments directly into the token buffer, so that large claims are only
+        // held once in serialized form and once in encoded form
+        let mut authenticated = vec![
+            0u8;
+            Base64UrlSafeNoPadding::encoded_len(jwt_header_json.len())?
+                + Base64UrlSafeNoPadding::encoded_len(claims_json.len())?
+        ];
+        let jwt_header_len =
+            Base64UrlSafeNoPadding::encode(&mut authenticated, jwt_header_json)?.len();
+        authenticated[jwt_header_len] = b'.';
+        let claims_len =
+            Base64UrlSafeNoPadding::encode(&mut authenticated[jwt_header_len + 1..], claims_json)?
+                .len();
+        authenticated.truncate(jwt_header_len + 1 + claims_len);
+        let authenticated = String::from_utf8(authenticated)?;
         let authentication_tag_or_signature = authentication_or_signature_fn(&authenticated)?;
         let mut token = authenticated;
         token.push('.');
@@ -98,64 +144,473 @@ impl Token {
         Ok(token)
     }
 
+    /// Verify a token, and attach the algorithm and key identifier of the token to errors
+    ///
+    /// The original error can still be retrieved with `error.downcast_ref::<JWTError>()`.
     pub(crate) fn verify<AuthenticationOrSignatureFn, CustomClaims: Serialize + DeserializeOwned>(
         jwt_alg_name: &'static str,
         token: &str,
-        options: Option<VerificationOptions>,
+        options: &VerificationOptions,
         authentication_or_signature_fn: AuthenticationOrSignatureFn,
     ) -> Result<JWTClaims<CustomClaims>, Error>
     where
         AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
     {
-        let options = options.unwrap_or_default();
-        let mut parts = token.split('.');
-        let jwt_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
-        ensure!(
-            jwt_header_b64.len() <= MAX_HEADER_LENGTH,
-            JWTError::HeaderTooLarge
-        );
-        let claims_b64 = parts.next().unwrap();
-        let authentication_tag_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
-        ensure!(parts.next().is_none(), JWTError::CompactEncodingError);
-        let jwt_header: JWTHeader = serde_json::from_slice(
-            &Base64UrlSafeNoPadding::decode_to_vec(jwt_header_b64, None).unwrap(),
-        )?;
+        let mut claims_json = vec![];
+        Self::verify_claims(
+            jwt_alg_name,
+            token,
+            options,
+            &mut claims_json,
+            authentication_or_signature_fn,
+        )
+        .map(|(claims, _)| claims)
+        .map_err(|e| error_with_context(e, jwt_alg_name, token))
+    }
+
+    pub(crate) fn verify_with_metadata<
+        AuthenticationOrSignatureFn,
+        CustomClaims: Serialize + DeserializeOwned,
+    >(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: &VerificationOptions,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<(JWTClaims<CustomClaims>, TokenMetadata), Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+    {
+        let mut claims_json = vec![];
+        let (claims, jwt_header) = Self::verify_claims(
+            jwt_alg_name,
+            token,
+            options,
+            &mut claims_json,
+            authentication_or_signature_fn,
+        )
+        .map_err(|e| error_with_context(e, jwt_alg_name, token))?;
+        let claim_count = serde_additions::keys::deserialize(
+            &mut serde_json::Deserializer::from_slice(&claims_json),
+        )
+        .map(|claim_names| claim_names.len())
+        .ok();
+        let metadata = TokenMetadata {
+            jwt_header,
+            issuer: claims.issuer.clone(),
+            token_len: token.len(),
+            claim_count,
+            verified_algorithm: Some(jwt_alg_name),
+        };
+        Ok((claims, metadata))
+    }
+
+    /// Verify the tag or signature of a token, and decode its payload into `claims_json`
+    fn verify_payload<AuthenticationOrSignatureFn>(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: &VerificationOptions,
+        claims_json: &mut Vec<u8>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<JWTHeader, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+    {
+        let (jwt_header_b64, claims_b64, authentication_tag_b64) = split_token(token)?;
+        let jwt_header = verify_header(jwt_alg_name, jwt_header_b64, options)?;
         ensure!(
-            jwt_header.algorithm == jwt_alg_name,
-            JWTError::AlgorithmMismatch
+            jwt_header.base64_encoded_payload != Some(false),
+            JWTError::PayloadEncodingMismatch
         );
-        if let Some(required_key_id) = &options.required_key_id {
-            if let Some(key_id) = &jwt_header.key_id {
-                ensure!(key_id == required_key_id, JWTError::KeyIdentifierMismatch);
-            } else {
-                bail!(JWTError::MissingJWTKeyIdentifier)
-            }
-        }
         let authentication_tag =
-            Base64UrlSafeNoPadding::decode_to_vec(&authentication_tag_b64, None)?;
+            Base64UrlSafeNoPadding::decode_to_vec(unpadded(options, authentication_tag_b64), None)?;
         let authenticated = &token[..jwt_header_b64.len() + 1 + claims_b64.len()];
+        // The payload is only decoded once the tag or signature has been verified
         authentication_or_signature_fn(authenticated, &authentication_tag)?;
-        let claims: JWTClaims<CustomClaims> =
-            serde_json::from_slice(&Base64UrlSafeNoPadding::decode_to_vec(&claims_b64, None)?)?;
-        claims.validate(&options)?;
+        decode_segment_into(unpadded(options, claims_b64), "payload", claims_json)?;
+        Ok(jwt_header)
+    }
+
+    /// Verify a token, and return its claims along with its header
+    fn verify_claims<AuthenticationOrSignatureFn, CustomClaims: Serialize + DeserializeOwned>(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: &VerificationOptions,
+        claims_json: &mut Vec<u8>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<(JWTClaims<CustomClaims>, JWTHeader), Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+    {
+        let jwt_header = Self::verify_payload(
+            jwt_alg_name,
+            token,
+            options,
+            claims_json,
+            authentication_or_signature_fn,
+        )?;
+        check_reserved_claim_collisions(options, claims_json)?;
+        let rfc3339_timestamps = options.rfc3339_timestamps;
+        #[cfg(not(feature = "chrono"))]
+        ensure!(!rfc3339_timestamps, JWTError::RFC3339TimestampsUnsupported);
+        let claims: JWTClaims<CustomClaims> = if options.lenient_audiences || rfc3339_timestamps {
+            let mut claims_value: serde_json::Value =
+                serde_json::from_slice(claims_json).map_err(JWTError::ClaimsDeserialization)?;
+            if options.lenient_audiences {
+                if let Some(serde_json::Value::Array(audiences)) = claims_value.get_mut("aud") {
+                    audiences.retain(|audience| audience.is_string());
+                }
+            }
+            #[cfg(feature = "chrono")]
+            if rfc3339_timestamps {
+                serde_additions::rfc3339::decode(&mut claims_value)?;
+            }
+            serde_json::from_value(claims_value)
+        } else {
+            serde_json::from_slice(claims_json)
+        }
+        .map_err(|e| {
+            if serde_additions::unix_timestamp::has_invalid_time_claim(claims_json) {
+                JWTError::InvalidTimeClaim
+            } else {
+                JWTError::ClaimsDeserialization(e)
+            }
+        })?;
+        claims.validate(options)?;
+        Ok((claims, jwt_header))
+    }
+
+    /// Verify a token, and return claims borrowed from `claims_json`
+    ///
+    /// Like `verify()`, this attaches the algorithm and key identifier of the token to errors.
+    pub(crate) fn verify_borrowed<'b, AuthenticationOrSignatureFn>(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: &VerificationOptions,
+        claims_json: &'b mut Vec<u8>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<BorrowedClaims<'b>, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+    {
+        Self::verify_borrowed_claims(
+            jwt_alg_name,
+            token,
+            options,
+            claims_json,
+            authentication_or_signature_fn,
+        )
+        .map_err(|e| error_with_context(e, jwt_alg_name, token))
+    }
+
+    fn verify_borrowed_claims<'b, AuthenticationOrSignatureFn>(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: &VerificationOptions,
+        claims_json: &'b mut Vec<u8>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<BorrowedClaims<'b>, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+    {
+        Self::verify_payload(
+            jwt_alg_name,
+            token,
+            options,
+            claims_json,
+            authentication_or_signature_fn,
+        )?;
+        check_reserved_claim_collisions(options, claims_json)?;
+        let claims: BorrowedClaims<'b> = serde_json::from_slice(claims_json).map_err(|e| {
+            if serde_additions::unix_timestamp::has_invalid_time_claim(claims_json) {
+                JWTError::InvalidTimeClaim
+            } else {
+                JWTError::ClaimsDeserialization(e)
+            }
+        })?;
+        claims.validate(options)?;
         Ok(claims)
     }
 
+    /// Return `true` if `token` uses the compact serialization: three non-empty base64url
+    /// strings, without padding nor whitespace, separated by dots
+    ///
+    /// Tokens created by this crate always satisfy this; it doesn't verify anything else.
+    pub fn is_compact(token: &str) -> bool {
+        let is_base64url = |part: &str| {
+            !part.is_empty()
+                && part
+                    .bytes()
+                    .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+        };
+        let parts: Vec<&str> = token.split('.').collect();
+        parts.len() == 3 && parts.into_iter().all(is_base64url)
+    }
+
+    /// Create a token with an unencoded, detached payload (RFC 7797)
+    ///
+    /// The compact serialization can't carry arbitrary bytes, so the payload is left out of the token.
+    pub(crate) fn build_unencoded_detached<AuthenticationOrSignatureFn>(
+        mut jwt_header: JWTHeader,
+        payload: &[u8],
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<String, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+    {
+        jwt_header.signature_type = None;
+        jwt_header.base64_encoded_payload = Some(false);
+        jwt_header.critical = Some(vec!["b64".to_string()]);
+        let jwt_header_json = serde_json::to_string(&jwt_header)?;
+        let jwt_header_b64 = Base64UrlSafeNoPadding::encode_to_string(jwt_header_json)?;
+        let mut signing_input = jwt_header_b64.as_bytes().to_vec();
+        signing_input.push(b'.');
+        signing_input.extend_from_slice(payload);
+        let authentication_tag_or_signature = authentication_or_signature_fn(&signing_input)?;
+        let mut token = jwt_header_b64;
+        token.push_str("..");
+        token.push_str(&Base64UrlSafeNoPadding::encode_to_string(
+            &authentication_tag_or_signature,
+        )?);
+        Ok(token)
+    }
+
+    /// Verify a token with an unencoded, detached payload (RFC 7797)
+    ///
+    /// The header is checked like the header of any other token. There are no claims, so the
+    /// options that only apply to claims are ignored.
+    pub(crate) fn verify_unencoded_detached<AuthenticationOrSignatureFn>(
+        jwt_alg_name: &'static str,
+        token: &str,
+        payload: &[u8],
+        options: &VerificationOptions,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<(), Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
+        let (jwt_header_b64, detached_payload, authentication_tag_b64) = split_token(token)?;
+        ensure!(detached_payload.is_empty(), JWTError::CompactEncodingError);
+        let jwt_header = verify_header(jwt_alg_name, jwt_header_b64, options)?;
+        let b64_is_critical = jwt_header
+            .critical
+            .as_ref()
+            .is_some_and(|critical| critical.iter().any(|x| x == "b64"));
+        ensure!(
+            jwt_header.base64_encoded_payload == Some(false) && b64_is_critical,
+            JWTError::PayloadEncodingMismatch
+        );
+        let authentication_tag =
+            Base64UrlSafeNoPadding::decode_to_vec(unpadded(options, authentication_tag_b64), None)?;
+        let mut signing_input = jwt_header_b64.as_bytes().to_vec();
+        signing_input.push(b'.');
+        signing_input.extend_from_slice(payload);
+        authentication_or_signature_fn(&signing_input, &authentication_tag)
+    }
+
+    /// Return the subject of a token, without verifying anything
+    ///
+    /// **The subject cannot be trusted.** This is only meant for logging and correlation purposes,
+    /// before or independently of the actual token verification.
+    pub fn peek_subject(token: &str) -> Option<String> {
+        let claims = decode_unverified_claims(token.split('.').nth(1)?)?;
+        claims.get("sub")?.as_str().map(|sub| sub.to_string())
+    }
+
     /// Decode token information that can be usedful prior to signature/tag verification
     pub fn decode_metadata(token: &str) -> Result<TokenMetadata, Error> {
+        let jwt_header = decode_header(token)?;
+
+        #[derive(Deserialize)]
+        struct Issuer {
+            iss: Option<String>,
+        }
+        let issuer = token
+            .split('.')
+            .nth(1)
+            .and_then(|claims_b64| Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None).ok())
+            .and_then(|claims_json| serde_json::from_slice::<Issuer>(&claims_json).ok())
+            .and_then(|x| x.iss);
+        Ok(TokenMetadata {
+            jwt_header,
+            issuer,
+            token_len: token.len(),
+            claim_count: None,
+            verified_algorithm: None,
+        })
+    }
+
+    /// Decode the header, claims and tag/signature of a token, without verifying anything
+    ///
+    /// This returns the raw, base64-decoded content of each segment, and is mostly useful for debugging.
+    #[allow(clippy::type_complexity)]
+    pub fn decode_parts(token: &str) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), Error> {
         let mut parts = token.split('.');
         let jwt_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
         ensure!(
             jwt_header_b64.len() <= MAX_HEADER_LENGTH,
             JWTError::HeaderTooLarge
         );
-        let jwt_header: JWTHeader = serde_json::from_slice(
-            &Base64UrlSafeNoPadding::decode_to_vec(jwt_header_b64, None).unwrap(),
-        )?;
-        Ok(TokenMetadata { jwt_header })
+        let claims_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let authentication_tag_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(parts.next().is_none(), JWTError::InvalidTokenFormat);
+        Ok((
+            Base64UrlSafeNoPadding::decode_to_vec(jwt_header_b64, None)?,
+            Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None)?,
+            Base64UrlSafeNoPadding::decode_to_vec(authentication_tag_b64, None)?,
+        ))
     }
 }
 
+/// Split a token into its header, payload and tag/signature segments
+fn split_token(token: &str) -> Result<(&str, &str, &str), Error> {
+    // Encrypted tokens use the compact serialization with five segments
+    ensure!(token.split('.').count() != 5, JWTError::NotAJws);
+    let mut parts = token.split('.');
+    let jwt_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+    ensure!(
+        jwt_header_b64.len() <= MAX_HEADER_LENGTH,
+        JWTError::HeaderTooLarge
+    );
+    let claims_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+    let authentication_tag_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+    ensure!(parts.next().is_none(), JWTError::InvalidTokenFormat);
+    Ok((jwt_header_b64, claims_b64, authentication_tag_b64))
+}
+
+/// Decode the header of a token, and check its algorithm and key identifier
+fn verify_header(
+    jwt_alg_name: &'static str,
+    jwt_header_b64: &str,
+    options: &VerificationOptions,
+) -> Result<JWTHeader, Error> {
+    let jwt_header: JWTHeader = serde_json::from_slice(&decode_segment(
+        unpadded(options, jwt_header_b64),
+        "header",
+    )?)?;
+    ensure!(jwt_header.encryption.is_none(), JWTError::NotAJws);
+    ensure!(
+        jwt_header.algorithm == jwt_alg_name,
+        JWTError::AlgorithmMismatch
+    );
+    ensure!(
+        !options.require_key_id || jwt_header.key_id.is_some(),
+        JWTError::RequiredKeyIdMissing
+    );
+    if let Some(required_key_id) = &options.required_key_id {
+        if let Some(key_id) = &jwt_header.key_id {
+            ensure!(key_id == required_key_id, JWTError::KeyIdentifierMismatch);
+        } else {
+            bail!(JWTError::MissingJWTKeyIdentifier)
+        }
+    }
+    Ok(jwt_header)
+}
+
+/// Remove the padding of a segment, if the verification options accept padded segments
+fn unpadded<'t>(options: &VerificationOptions, segment_b64: &'t str) -> &'t str {
+    match options.accept_padded_segments {
+        true => strip_padding(segment_b64),
+        false => segment_b64,
+    }
+}
+
+/// Attach the expected algorithm, and the algorithm and key identifier of a token to an error
+///
+/// The message of the original error comes first, so that the failed check is still part of the
+/// `Display` output of the returned error.
+fn error_with_context(e: Error, jwt_alg_name: &str, token: &str) -> Error {
+    // Only the header is decoded here: the payload of a token that failed verification must
+    // not be parsed
+    let jwt_header = decode_header(token).ok();
+    let algorithm = jwt_header
+        .as_ref()
+        .map(|x| x.algorithm.as_str())
+        .unwrap_or("unknown");
+    let key_id = jwt_header
+        .as_ref()
+        .and_then(|x| x.key_id.as_deref())
+        .unwrap_or("none");
+    let context = format!(
+        "{}: expected algorithm {}, token algorithm {}, kid {}",
+        e, jwt_alg_name, algorithm, key_id
+    );
+    e.context(context)
+}
+
+/// Reject claims colliding with reserved claim names, if the verification options require it
+fn check_reserved_claim_collisions(
+    options: &VerificationOptions,
+    claims_json: &[u8],
+) -> Result<(), Error> {
+    if !options.reject_reserved_claim_collisions {
+        return Ok(());
+    }
+    let claim_names =
+        serde_additions::keys::deserialize(&mut serde_json::Deserializer::from_slice(claims_json))
+            .map_err(JWTError::ClaimsDeserialization)?;
+    if let Some(name) = reserved_claim_collision(claim_names.iter().map(|x| x.as_str())) {
+        bail!(JWTError::ReservedClaimCollision(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Decode the header of a token, without looking at the other segments
+fn decode_header(token: &str) -> Result<JWTHeader, Error> {
+    let jwt_header_b64 = token
+        .split('.')
+        .next()
+        .ok_or(JWTError::CompactEncodingError)?;
+    ensure!(
+        jwt_header_b64.len() <= MAX_HEADER_LENGTH,
+        JWTError::HeaderTooLarge
+    );
+    Ok(serde_json::from_slice(&decode_segment(
+        jwt_header_b64,
+        "header",
+    )?)?)
+}
+
+/// Remove up to two trailing `=` padding characters from a base64url-encoded segment
+fn strip_padding(segment_b64: &str) -> &str {
+    let unpadded = segment_b64.trim_end_matches('=');
+    if segment_b64.len() - unpadded.len() <= 2 {
+        unpadded
+    } else {
+        segment_b64
+    }
+}
+
+/// Decode a base64url-encoded JSON segment, and check that it is valid UTF-8
+fn decode_segment(segment_b64: &str, segment: &'static str) -> Result<Vec<u8>, Error> {
+    let mut segment_json = vec![];
+    decode_segment_into(segment_b64, segment, &mut segment_json)?;
+    Ok(segment_json)
+}
+
+/// Decode a base64url-encoded JSON segment into an existing buffer, that can be reused
+fn decode_segment_into(
+    segment_b64: &str,
+    segment: &'static str,
+    segment_json: &mut Vec<u8>,
+) -> Result<(), Error> {
+    segment_json.clear();
+    segment_json.resize(segment_b64.len() / 4 * 3 + 3, 0);
+    let len = Base64UrlSafeNoPadding::decode(segment_json, segment_b64, None)?.len();
+    segment_json.truncate(len);
+    ensure!(
+        std::str::from_utf8(segment_json).is_ok(),
+        JWTError::InvalidUtf8(segment)
+    );
+    Ok(())
+}
+
+fn decode_unverified_claims(
+    claims_b64: &str,
+) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let claims_json = Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None).ok()?;
+    serde_json::from_slice(&claims_json).ok()
+}
+
 #[test]
 fn should_verify_token() {
     use crate::prelude::*;
@@ -170,10 +625,12 @@ fn should_verify_token() {
     let nonce = claims.create_nonce();
     let token = key.authenticate(claims).unwrap();
 
-    let mut options = VerificationOptions::default();
-    options.required_nonce = Some(nonce);
-    options.required_issuer = Some(issuer.to_string());
-    options.required_audience = Some(audience.to_string());
+    let options = VerificationOptions {
+        required_nonce: Some(nonce),
+        required_issuer: Some(issuer.to_string()),
+        required_audience: Some(audience.to_string()),
+        ..Default::default()
+    };
     key.verify_token::<NoCustomClaims>(&token, Some(options))
         .unwrap();
 }
@@ -192,8 +649,10 @@ fn multiple_audiences() {
     let claims = Claims::create(Duration::from_mins(10)).with_audiences(audiences);
     let token = key.authenticate(claims).unwrap();
 
-    let mut options = VerificationOptions::default();
-    options.required_audience = Some("audience 1".to_string());
+    let options = VerificationOptions {
+        required_audience: Some("audience 1".to_string()),
+        ..Default::default()
+    };
     key.verify_token::<NoCustomClaims>(&token, Some(options))
         .unwrap();
 }
@@ -221,3 +680,803 @@ fn explicitly_empty_audiences() {
     let decoded = key.verify_token::<NoCustomClaims>(&token, None).unwrap();
     assert!(decoded.audiences.is_none());
 }
+
+#[test]
+fn shared_verification_options() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let options = VerificationOptions {
+        required_issuer: Some("issuer".to_string()),
+        ..Default::default()
+    };
+    for _ in 0..3 {
+        let claims = Claims::create(Duration::from_mins(10)).with_issuer("issuer");
+        let token = key.authenticate(claims).unwrap();
+        key.verify_token_with_options::<NoCustomClaims>(&token, &options)
+            .unwrap();
+    }
+}
+
+#[test]
+fn footer() {
+    use crate::prelude::*;
+
+    let key_pair = Ed25519KeyPair::generate();
+    let claims = Claims::create(Duration::from_mins(10));
+    let token = key_pair.sign_with_footer(claims, "footer data").unwrap();
+    let (_claims, footer) = key_pair
+        .public_key()
+        .verify_token_with_footer::<NoCustomClaims>(&token, None)
+        .unwrap();
+    assert_eq!(footer.as_deref(), Some("footer data"));
+
+    let claims = Claims::create(Duration::from_mins(10));
+    let token = key_pair.sign(claims).unwrap();
+    let (_claims, footer) = key_pair
+        .public_key()
+        .verify_token_with_footer::<NoCustomClaims>(&token, None)
+        .unwrap();
+    assert!(footer.is_none());
+}
+
+#[test]
+fn content_type() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let metadata = Token::decode_metadata(&token).unwrap();
+    assert!(metadata.content_type().is_none());
+
+    let key_pair = ES256KeyPair::generate();
+    let claims = Claims::create(Duration::from_mins(10));
+    let token = key_pair.sign_with_content_type(claims, "JWT").unwrap();
+    let metadata = Token::decode_metadata(&token).unwrap();
+    assert_eq!(metadata.content_type(), Some("JWT"));
+    key_pair
+        .public_key()
+        .verify_token::<NoCustomClaims>(&token, None)
+        .unwrap();
+}
+
+#[test]
+fn custom_header() {
+    use crate::prelude::*;
+
+    let key_pair = Ed25519KeyPair::generate().with_key_id("key id");
+    let jwt_header = JWTHeader {
+        algorithm: "none".to_string(),
+        content_type: Some("JWT".to_string()),
+        footer: Some("footer data".to_string()),
+        ..Default::default()
+    };
+    let claims = Claims::create(Duration::from_mins(10));
+    let token = key_pair.sign_with_header(&claims, jwt_header).unwrap();
+    let (_claims, metadata) = key_pair
+        .public_key()
+        .verify_token_with_metadata::<NoCustomClaims>(&token, None)
+        .unwrap();
+    assert_eq!(metadata.algorithm(), "EdDSA");
+    assert_eq!(metadata.key_id(), Some("key id"));
+    assert_eq!(metadata.content_type(), Some("JWT"));
+    assert_eq!(metadata.footer(), Some("footer data"));
+}
+
+#[test]
+fn lenient_audiences() {
+    use crate::prelude::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct NonCompliantAudience {
+        id: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct NonCompliantClaims {
+        aud: Vec<NonCompliantAudience>,
+    }
+
+    let key = HS256Key::generate();
+    let custom = NonCompliantClaims {
+        aud: vec![NonCompliantAudience {
+            id: "svc".to_string(),
+        }],
+    };
+    let claims = Claims::with_custom_claims(custom, Duration::from_mins(10));
+    let token = key.authenticate(claims).unwrap();
+
+    assert!(key.verify_token::<NoCustomClaims>(&token, None).is_err());
+    let options = VerificationOptions {
+        lenient_audiences: true,
+        ..Default::default()
+    };
+    let claims = key
+        .verify_token::<NoCustomClaims>(&token, Some(options))
+        .unwrap();
+    assert!(claims.audiences.unwrap().into_set().is_empty());
+}
+
+#[test]
+fn sign_returning_claims() {
+    use crate::prelude::*;
+
+    let key_pair = ES256KeyPair::generate();
+    let claims = Claims::create(Duration::from_mins(10))
+        .with_jwt_id("jwt id")
+        .with_invalid_before(UnixTimeStamp::from_millis(1_500));
+    let (token, claims) = key_pair.sign_returning_claims(claims).unwrap();
+    assert_eq!(claims.invalid_before, Some(UnixTimeStamp::from_secs(1)));
+    let decoded = key_pair
+        .public_key()
+        .verify_token::<NoCustomClaims>(&token, None)
+        .unwrap();
+    assert_eq!(decoded.issued_at, claims.issued_at);
+    assert_eq!(decoded.expires_at, claims.expires_at);
+    assert_eq!(decoded.invalid_before, claims.invalid_before);
+    assert_eq!(decoded.jwt_id, claims.jwt_id);
+}
+
+#[test]
+fn reserved_claim_collisions() {
+    use crate::prelude::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct CustomClaims {
+        expires: u64,
+    }
+
+    let key = HS256Key::generate();
+    let claims = Claims::with_custom_claims(CustomClaims { expires: 0 }, Duration::from_mins(10));
+    let token = key.authenticate(claims).unwrap();
+    key.verify_token::<CustomClaims>(&token, None).unwrap();
+    let options = VerificationOptions {
+        reject_reserved_claim_collisions: true,
+        ..Default::default()
+    };
+    assert!(key
+        .verify_token::<CustomClaims>(&token, Some(options.clone()))
+        .is_err());
+
+    let claims = Claims::create(Duration::from_mins(10)).with_issuer("issuer");
+    let token = key.authenticate(claims).unwrap();
+    key.verify_token::<NoCustomClaims>(&token, Some(options))
+        .unwrap();
+}
+
+#[test]
+fn dynamic_claims() {
+    use crate::prelude::*;
+    use std::collections::HashMap;
+
+    let mut custom = HashMap::new();
+    custom.insert("user_country".to_string(), "FR".to_string());
+    let key = HS256Key::generate();
+    let claims = Claims::with_custom_claims(custom, Duration::from_mins(10)).with_issuer("issuer");
+    let token = key.authenticate(claims).unwrap();
+    let claims = key.verify_token::<DynamicClaims>(&token, None).unwrap();
+    assert_eq!(claims.issuer.as_deref(), Some("issuer"));
+    assert!(claims.expires_at.is_some());
+    assert_eq!(claims.custom.len(), 1);
+    assert_eq!(claims.custom["user_country"], "FR");
+}
+
+#[test]
+fn claims_deserialization_error() {
+    use crate::prelude::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct CustomClaims {
+        user_is_admin: bool,
+    }
+
+    let key = HS256Key::generate();
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let res = key.verify_token::<CustomClaims>(&token, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::ClaimsDeserialization(_))
+    ));
+    let res = HS256Key::generate().verify_token::<CustomClaims>(&token, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidAuthenticationTag)
+    ));
+}
+
+#[test]
+fn signature_verified_before_deserialization() {
+    use crate::prelude::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static DESERIALIZED: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Serialize)]
+    struct CustomClaims {
+        is_admin: bool,
+    }
+
+    impl<'de> Deserialize<'de> for CustomClaims {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            #[derive(Deserialize)]
+            struct Fields {
+                is_admin: bool,
+            }
+            DESERIALIZED.store(true, Ordering::SeqCst);
+            Fields::deserialize(deserializer).map(|x| CustomClaims {
+                is_admin: x.is_admin,
+            })
+        }
+    }
+
+    let key = HS256Key::generate();
+    let claims =
+        Claims::with_custom_claims(CustomClaims { is_admin: false }, Duration::from_mins(10));
+    let token = key.authenticate(claims).unwrap();
+    let parts: Vec<&str> = token.split('.').collect();
+    let tampered_claims = Base64UrlSafeNoPadding::encode_to_string(
+        String::from_utf8(Base64UrlSafeNoPadding::decode_to_vec(parts[1], None).unwrap())
+            .unwrap()
+            .replace("false", "true"),
+    )
+    .unwrap();
+    let tampered = format!("{}.{}.{}", parts[0], tampered_claims, parts[2]);
+    let res = key.verify_token::<CustomClaims>(&tampered, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidAuthenticationTag)
+    ));
+    assert!(!DESERIALIZED.load(Ordering::SeqCst));
+
+    let claims = key.verify_token::<CustomClaims>(&token, None).unwrap();
+    assert!(!claims.custom.is_admin);
+    assert!(DESERIALIZED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn decode_parts() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let claims = Claims::create(Duration::from_mins(10)).with_issuer("issuer");
+    let token = key.authenticate(claims).unwrap();
+    let (jwt_header, claims, authentication_tag) = Token::decode_parts(&token).unwrap();
+    let jwt_header: serde_json::Value = serde_json::from_slice(&jwt_header).unwrap();
+    assert_eq!(jwt_header["alg"], "HS256");
+    let claims: serde_json::Value = serde_json::from_slice(&claims).unwrap();
+    assert_eq!(claims["iss"], "issuer");
+    assert_eq!(authentication_tag.len(), 32);
+    assert!(Token::decode_parts("a.b").is_err());
+    assert!(Token::decode_parts("a.b.c.d").is_err());
+}
+
+#[test]
+fn require_key_id() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let options = VerificationOptions {
+        require_key_id: true,
+        ..Default::default()
+    };
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let res = key.verify_token::<NoCustomClaims>(&token, Some(options.clone()));
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::RequiredKeyIdMissing)
+    ));
+    let key = key.with_key_id("key id");
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    key.verify_token::<NoCustomClaims>(&token, Some(options))
+        .unwrap();
+}
+
+#[test]
+fn stable_header_encoding() {
+    use crate::prelude::*;
+
+    let key = HS256Key::from_bytes(b"key").with_key_id("key id");
+    let token = key
+        .authenticate_with_footer(Claims::create(Duration::from_mins(10)), "footer")
+        .unwrap();
+    let (jwt_header, _, _) = Token::decode_parts(&token).unwrap();
+    assert_eq!(
+        jwt_header,
+        br#"{"alg":"HS256","kid":"key id","typ":"JWT","footer":"footer"}"#
+    );
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn rfc3339_timestamps() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let claims = Claims::create(Duration::from_mins(10))
+        .with_issued_at(UnixTimeStamp::from_secs(1_600_000_000))
+        .with_rfc3339_timestamps();
+    let token = key.authenticate(claims).unwrap();
+    let (_, claims_json, _) = Token::decode_parts(&token).unwrap();
+    let claims_value: serde_json::Value = serde_json::from_slice(&claims_json).unwrap();
+    assert_eq!(claims_value["iat"], "2020-09-13T12:26:40Z");
+    assert!(key.verify_token::<NoCustomClaims>(&token, None).is_err());
+    let options = VerificationOptions {
+        rfc3339_timestamps: true,
+        ..Default::default()
+    };
+    let claims = key
+        .verify_token::<NoCustomClaims>(&token, Some(options))
+        .unwrap();
+    assert_eq!(
+        claims.issued_at,
+        Some(UnixTimeStamp::from_secs(1_600_000_000))
+    );
+}
+
+#[cfg(not(feature = "chrono"))]
+#[test]
+fn rfc3339_timestamps_unsupported() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let mut claims = Claims::create(Duration::from_mins(10));
+    claims.rfc3339_timestamps = true;
+    let res = key.authenticate(claims);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::RFC3339TimestampsUnsupported)
+    ));
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let options = VerificationOptions {
+        rfc3339_timestamps: true,
+        ..Default::default()
+    };
+    let res = key.verify_token::<NoCustomClaims>(&token, Some(options));
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::RFC3339TimestampsUnsupported)
+    ));
+}
+
+/// Build an HS256 token from a raw header and payload, that may not be valid JSON
+#[cfg(test)]
+fn hs256_token(key: &crate::prelude::HS256Key, header_json: &str, payload: &[u8]) -> String {
+    use crate::prelude::*;
+
+    let authenticated = format!(
+        "{}.{}",
+        Base64UrlSafeNoPadding::encode_to_string(header_json).unwrap(),
+        Base64UrlSafeNoPadding::encode_to_string(payload).unwrap()
+    );
+    let authentication_tag = key.authentication_tag(&authenticated);
+    format!(
+        "{}.{}",
+        authenticated,
+        Base64UrlSafeNoPadding::encode_to_string(authentication_tag).unwrap()
+    )
+}
+
+#[test]
+fn empty_payload() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let token = hs256_token(&key, r#"{"alg":"HS256","typ":"JWT"}"#, b"{}");
+    let claims = key.verify_token::<NoCustomClaims>(&token, None).unwrap();
+    assert!(claims.issued_at.is_none());
+    assert!(claims.expires_at.is_none());
+    assert!(claims.invalid_before.is_none());
+    assert!(claims.issuer.is_none());
+    assert!(claims.subject.is_none());
+    assert!(claims.audiences.is_none());
+    assert!(claims.jwt_id.is_none());
+    assert!(claims.nonce.is_none());
+}
+
+#[test]
+fn negative_time_claims() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    for time_claims in &[
+        r#"{"iat":-1}"#,
+        r#"{"nbf":-9223372036854775808}"#,
+        r#"{"exp":99999999999999999999}"#,
+        r#"{"exp":1.5}"#,
+    ] {
+        let token = hs256_token(&key, r#"{"alg":"HS256"}"#, time_claims.as_bytes());
+        let res = key.verify_token::<NoCustomClaims>(&token, None);
+        assert!(matches!(
+            res.err().unwrap().downcast_ref::<JWTError>(),
+            Some(JWTError::InvalidTimeClaim)
+        ));
+    }
+}
+
+#[test]
+fn verify_token_with_metadata() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let claims = Claims::create(Duration::from_mins(10)).with_issuer("issuer");
+    let token = key.authenticate(claims).unwrap();
+    let (claims, metadata) = key
+        .verify_token_with_metadata::<NoCustomClaims>(&token, None)
+        .unwrap();
+    assert_eq!(claims.issuer.as_deref(), Some("issuer"));
+    assert_eq!(metadata.issuer(), Some("issuer"));
+    assert_eq!(metadata.token_len(), token.len());
+    assert_eq!(metadata.claim_count(), Some(4));
+    assert_eq!(metadata.verified_algorithm(), Some("HS256"));
+    assert_eq!(metadata.algorithm(), "HS256");
+    let metadata = Token::decode_metadata(&token).unwrap();
+    assert_eq!(metadata.issuer(), Some("issuer"));
+    assert_eq!(metadata.claim_count(), None);
+    assert_eq!(metadata.verified_algorithm(), None);
+}
+
+#[test]
+fn ignored_custom_claims() {
+    use crate::prelude::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct CustomClaims {
+        user_is_admin: bool,
+        user_country: String,
+    }
+
+    let key_pair = Ed25519KeyPair::generate();
+    let custom = CustomClaims {
+        user_is_admin: false,
+        user_country: "FR".to_string(),
+    };
+    let claims = Claims::with_custom_claims(custom, Duration::from_mins(10)).with_issuer("issuer");
+    let token = key_pair.sign(claims).unwrap();
+    let options = VerificationOptions {
+        required_issuer: Some("issuer".to_string()),
+        ..Default::default()
+    };
+    let claims = key_pair
+        .public_key()
+        .verify_token::<NoCustomClaims>(&token, Some(options))
+        .unwrap();
+    assert_eq!(claims.issuer.as_deref(), Some("issuer"));
+}
+
+#[test]
+fn unencoded_payload() {
+    use crate::prelude::*;
+
+    let payload = [0u8, 1, 2, b'.', 0xff];
+    let key_pair = Ed25519KeyPair::generate();
+    let public_key = key_pair.public_key();
+    let token = key_pair.sign_unencoded_detached(&payload).unwrap();
+    assert!(token.contains(".."));
+    let metadata = Token::decode_metadata(&token).unwrap();
+    assert_eq!(metadata.critical(), Some(&["b64".to_string()][..]));
+    public_key
+        .verify_unencoded_detached(&token, &payload, None)
+        .unwrap();
+    assert!(public_key
+        .verify_unencoded_detached(&token, b"other payload", None)
+        .is_err());
+    let res = public_key.verify_token::<NoCustomClaims>(&token, None);
+    assert!(res.is_err());
+
+    let key = HS256Key::generate();
+    let token = key.authenticate_unencoded_detached(&payload).unwrap();
+    key.verify_unencoded_detached(&token, &payload, None)
+        .unwrap();
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let res = key.verify_unencoded_detached(&token, &payload, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::CompactEncodingError)
+    ));
+
+    let key = HS256Key::generate().with_key_id("key id");
+    let token = key.authenticate_unencoded_detached(&payload).unwrap();
+    let options = VerificationOptions::default().with_required_key_id("key id");
+    key.verify_unencoded_detached(&token, &payload, Some(options))
+        .unwrap();
+    let options = VerificationOptions::default().with_required_key_id("other key id");
+    let res = key.verify_unencoded_detached(&token, &payload, Some(options));
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::KeyIdentifierMismatch)
+    ));
+    let token = HS256Key::generate()
+        .authenticate_unencoded_detached(&payload)
+        .unwrap();
+    let options = VerificationOptions {
+        require_key_id: true,
+        ..Default::default()
+    };
+    let res = key.verify_unencoded_detached(&token, &payload, Some(options));
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::RequiredKeyIdMissing)
+    ));
+}
+
+#[test]
+fn peek_subject() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let claims = Claims::create(Duration::from_mins(10)).with_subject("user");
+    let token = key.authenticate(claims).unwrap();
+    assert_eq!(Token::peek_subject(&token).as_deref(), Some("user"));
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    assert!(Token::peek_subject(&token).is_none());
+    assert!(Token::peek_subject("not a token").is_none());
+}
+
+#[test]
+fn pretty_printed_json() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let token = hs256_token(
+        &key,
+        "{\n  \"typ\" : \"JWT\",\r\n\t\"alg\": \"HS256\"\n}\n",
+        b"{\n  \"iss\" : \"issuer\"\n}",
+    );
+    let metadata = Token::decode_metadata(&token).unwrap();
+    assert_eq!(metadata.algorithm(), "HS256");
+    assert_eq!(metadata.signature_type(), Some("JWT"));
+    let claims = key.verify_token::<NoCustomClaims>(&token, None).unwrap();
+    assert_eq!(claims.issuer.as_deref(), Some("issuer"));
+}
+
+#[test]
+fn verification_error_context() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate().with_key_id("key id");
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let err = HS256Key::generate()
+        .verify_token::<NoCustomClaims>(&token, None)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidAuthenticationTag)
+    ));
+    let message = err.to_string();
+    assert!(message.starts_with("Authentication tag didn't verify"));
+    assert!(message.contains("expected algorithm HS256"));
+    assert!(message.contains("kid key id"));
+}
+
+#[test]
+fn malformed_tokens() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let trailing_dot = format!("{}.", token);
+    let extra_segment = format!("{}.extra", token);
+    let header_b64 = token.split('.').next().unwrap();
+    for malformed in &[
+        trailing_dot.as_str(),
+        extra_segment.as_str(),
+        header_b64,
+        "a.b.c.d",
+        "!.b.c",
+        "",
+    ] {
+        let res = key.verify_token::<NoCustomClaims>(malformed, None);
+        assert!(res.is_err());
+        assert!(Token::decode_parts(malformed).is_err());
+    }
+    for malformed in &[trailing_dot.as_str(), extra_segment.as_str(), "a.b.c.d"] {
+        let res = key.verify_token::<NoCustomClaims>(malformed, None);
+        assert!(matches!(
+            res.err().unwrap().downcast_ref::<JWTError>(),
+            Some(JWTError::InvalidTokenFormat)
+        ));
+    }
+    let res = key.verify_token::<NoCustomClaims>(header_b64, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::CompactEncodingError)
+    ));
+    assert!(Token::decode_metadata("!.b.c").is_err());
+}
+
+#[test]
+fn claims_to_json() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let mut custom = DynamicClaims::new();
+    custom.insert("user_country".to_string(), "FR".into());
+    let claims = Claims::with_custom_claims(custom, Duration::from_mins(10))
+        .with_audience("audience")
+        .with_subject("subject");
+    let token = key.authenticate(claims).unwrap();
+    let claims = key.verify_token::<DynamicClaims>(&token, None).unwrap();
+    let (_, claims_json, _) = Token::decode_parts(&token).unwrap();
+    assert_eq!(claims.to_json().unwrap().as_bytes(), &claims_json[..]);
+}
+
+#[test]
+fn large_claims() {
+    use crate::prelude::*;
+    let key = HS256Key::generate();
+    for len in 10_000..10_003 {
+        let claims = Claims::create(Duration::from_secs(10)).with_subject("x".repeat(len));
+        let token = key.authenticate(claims).unwrap();
+        let claims = key.verify_token::<NoCustomClaims>(&token, None).unwrap();
+        assert_eq!(claims.subject.unwrap().len(), len);
+    }
+}
+
+#[test]
+fn invalid_utf8() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let payloads: [&[u8]; 4] = [
+        b"{\"sub\":\"\xff\"}",
+        b"{\"sub\":\"\xc3\"}",
+        b"{\"\xed\xa0\x80\":1}",
+        b"\xfe\xff{}",
+    ];
+    for payload in payloads.iter() {
+        let token = hs256_token(&key, r#"{"alg":"HS256"}"#, payload);
+        let res = key.verify_token::<NoCustomClaims>(&token, None);
+        assert!(matches!(
+            res.err().unwrap().downcast_ref::<JWTError>(),
+            Some(JWTError::InvalidUtf8("payload"))
+        ));
+    }
+
+    let token = format!(
+        "{}.e30.e30",
+        Base64UrlSafeNoPadding::encode_to_string(b"{\"alg\":\"\xff\"}").unwrap()
+    );
+    let res = key.verify_token::<NoCustomClaims>(&token, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidUtf8("header"))
+    ));
+}
+
+#[test]
+fn borrowed_claims() {
+    use crate::prelude::*;
+    use std::borrow::Cow;
+
+    let key = HS256Key::generate();
+    let claims = Claims::create(Duration::from_mins(10))
+        .with_issuer("issuer")
+        .with_subject("sub\"ject")
+        .with_audiences_iter(vec!["audience"]);
+    let token = key.authenticate(claims).unwrap();
+    let mut payload = vec![];
+    let options = VerificationOptions::default()
+        .with_required_issuer("issuer")
+        .with_required_audience("audience");
+    let claims = key
+        .verify_token_borrowed(&token, &options, &mut payload)
+        .unwrap();
+    assert!(matches!(claims.issuer, Some(Cow::Borrowed("issuer"))));
+    assert!(matches!(&claims.subject, Some(Cow::Owned(x)) if x == "sub\"ject"));
+    assert_eq!(claims.audiences.as_ref().unwrap().first(), Some("audience"));
+    assert!(claims.expires_at.is_some());
+
+    let options = VerificationOptions::default().with_required_issuer("other issuer");
+    let err = key
+        .verify_token_borrowed(&token, &options, &mut payload)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<JWTError>(),
+        Some(JWTError::RequiredIssuerMismatch)
+    ));
+    assert!(err.to_string().contains("expected algorithm HS256"));
+    let res = HS256Key::generate().verify_token_borrowed(
+        &token,
+        &VerificationOptions::default(),
+        &mut payload,
+    );
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidAuthenticationTag)
+    ));
+}
+
+#[test]
+fn encrypted_tokens() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let header_b64 =
+        Base64UrlSafeNoPadding::encode_to_string(r#"{"alg":"dir","enc":"A256GCM"}"#).unwrap();
+    let jwe = format!("{}..aXY.Y2lwaGVydGV4dA.dGFn", header_b64);
+    let res = key.verify_token::<NoCustomClaims>(&jwe, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::NotAJws)
+    ));
+
+    let header_b64 =
+        Base64UrlSafeNoPadding::encode_to_string(r#"{"alg":"HS256","enc":"A256GCM"}"#).unwrap();
+    let token = format!("{}.e30.dGFn", header_b64);
+    let res = key.verify_token::<NoCustomClaims>(&token, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::NotAJws)
+    ));
+}
+
+#[test]
+fn truncated_authentication_tag() {
+    use crate::prelude::*;
+
+    let key = HS256Key::generate();
+    let token = key
+        .authenticate(Claims::create(Duration::from_secs(10)))
+        .unwrap();
+    let token = format!("{}AA", &token[..token.rfind('.').unwrap() + 1]);
+    let res = key.verify_token::<NoCustomClaims>(&token, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidAuthenticationTag)
+    ));
+    let res = HS256Key::verify_token_with_any_key::<NoCustomClaims>(&[key], &token, None);
+    assert!(matches!(
+        res.err().unwrap().downcast_ref::<JWTError>(),
+        Some(JWTError::InvalidAuthenticationTag)
+    ));
+}
+
+#[test]
+fn padded_segments() {
+    use crate::prelude::*;
+    use ct_codecs::Base64UrlSafe;
+
+    let key = HS256Key::generate();
+    let header_b64 = Base64UrlSafe::encode_to_string(r#"{"alg":"HS256"}"#).unwrap();
+    let claims = Claims::create(Duration::from_mins(10)).with_subject("padded");
+    let claims_b64 = Base64UrlSafe::encode_to_string(claims.to_json().unwrap()).unwrap();
+    let authenticated = format!("{}.{}", header_b64, claims_b64);
+    let tag_b64 = Base64UrlSafe::encode_to_string(key.authentication_tag(&authenticated)).unwrap();
+    assert!(tag_b64.ends_with('='));
+    let token = format!("{}.{}", authenticated, tag_b64);
+
+    assert!(key.verify_token::<NoCustomClaims>(&token, None).is_err());
+    let options = VerificationOptions {
+        accept_padded_segments: true,
+        ..Default::default()
+    };
+    let claims = key
+        .verify_token::<NoCustomClaims>(&token, Some(options.clone()))
+        .unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("padded"));
+
+    let token = format!("{}.{}==", authenticated, tag_b64);
+    assert!(key
+        .verify_token::<NoCustomClaims>(&token, Some(options))
+        .is_err());
+}